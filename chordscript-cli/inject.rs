@@ -0,0 +1,254 @@
+// Replaces the generated block of a config between the two marker lines, so
+// the rest of the config can still be edited by hand
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{fmt, fs, process};
+
+pub const BEGIN_MARKER: &str = "########## automatically generated begin ##########";
+pub const CLOSE_MARKER: &str = "########## automatically generated close ##########";
+
+#[derive(Debug)]
+pub enum InjectError {
+    Io(io::Error),
+    Missing(&'static str),
+    Duplicate(&'static str, usize, usize), // Rows of the first two matches
+    OutOfOrder,
+}
+
+impl fmt::Display for InjectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::Missing(marker) => write!(f, "Could not find the line:\n{}", marker),
+            Self::Duplicate(marker, first, second) => write!(
+                f,
+                "The marker is on both line {} and line {}, it must appear once:\n{}",
+                first, second, marker
+            ),
+            Self::OutOfOrder => write!(
+                f,
+                "The close marker must come after the begin marker:\n{}\n{}",
+                BEGIN_MARKER, CLOSE_MARKER
+            ),
+        }
+    }
+}
+
+// The byte range strictly between the marker lines and the row (1-indexed)
+// of the first line after the begin marker
+fn find_block(config: &str) -> Result<(usize, usize, usize), InjectError> {
+    // (row, start of line, end of line including the newline)
+    let find = |marker: &'static str| {
+        let mut found: Option<(usize, usize, usize)> = None;
+        let mut index = 0;
+        for (row, line) in config.split_inclusive('\n').enumerate() {
+            if line.trim_end_matches(['\n', '\r']) == marker {
+                if let Some((prev_row, _, _)) = found {
+                    return Err(InjectError::Duplicate(marker, prev_row + 1, row + 1));
+                }
+                found = Some((row, index, index + line.len()));
+            }
+            index += line.len();
+        }
+        found.ok_or(InjectError::Missing(marker))
+    };
+
+    let (begin_row, _, begin) = find(BEGIN_MARKER)?;
+    let (_, close, _) = find(CLOSE_MARKER)?;
+    if close < begin {
+        Err(InjectError::OutOfOrder)
+    } else {
+        Ok((begin, close, begin_row + 2))
+    }
+}
+
+// The generated text always ends the block with a newline so that the close
+// marker stays on its own line
+fn with_newline(generated: &str) -> &'static str {
+    if generated.is_empty() || generated.ends_with('\n') { "" } else { "\n" }
+}
+
+// Writes into a temporary file beside 'path' first so that a crash never
+// leaves a half-written config. A symbolic link is followed, so the file it
+// points to is replaced rather than the link, and keeps its permissions.
+pub fn write_atomic(path: &str, contents: &str) -> io::Result<()> {
+    let target = resolve_links(Path::new(path))?;
+    let (temp, mut file) = create_temp(&target)?;
+    let result = file
+        .write_all(contents.as_bytes())
+        .and_then(|()| match fs::metadata(&target) {
+            Ok(metadata) => fs::set_permissions(&temp, metadata.permissions()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err),
+        })
+        .and_then(|()| {
+            drop(file);
+            fs::rename(&temp, &target)
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+// The file at the end of any symbolic links, which need not exist yet
+fn resolve_links(path: &Path) -> io::Result<PathBuf> {
+    match fs::canonicalize(path) {
+        Ok(resolved) => Ok(resolved),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => match fs::read_link(path) {
+            // A link to a file that is yet to be written
+            Ok(link) => Ok(path.parent().unwrap_or(Path::new("")).join(link)),
+            Err(_) => Ok(path.to_path_buf()),
+        },
+        Err(err) => Err(err),
+    }
+}
+
+static TEMP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+// A new file beside 'target' that no other write (even from another
+// process) is using
+fn create_temp(target: &Path) -> io::Result<(PathBuf, fs::File)> {
+    let name = target.file_name().map_or("output".into(), |name| name.to_string_lossy());
+    loop {
+        let count = TEMP_COUNT.fetch_add(1, Ordering::Relaxed);
+        let temp = target.with_file_name(format!(".{}.{}-{}.chordscript-tmp", name, process::id(), count));
+        match fs::OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((temp, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+// 'is_backup' keeps the config from before in '{path}.bak'
+pub fn inject(path: &str, generated: &str, is_dry_run: bool, is_backup: bool) -> Result<(), InjectError> {
+    let config = fs::read_to_string(path).map_err(InjectError::Io)?;
+    let (begin, close, row) = find_block(&config)?;
+    let old = &config[begin..close];
+    let new = [generated, with_newline(generated)].concat();
+
+    if is_dry_run {
+        print!("{}", Diff { path, old, new: &new, row });
+    } else if old != new {
+        let updated = [&config[..begin], &new, &config[close..]].concat();
+        if is_backup {
+            backup(path)?;
+        }
+        write_atomic(path, &updated).map_err(InjectError::Io)?;
+    }
+    Ok(())
+}
+
+pub fn backup(path: &str) -> Result<(), InjectError> {
+    fs::copy(path, format!("{}.bak", path)).map(|_| ()).map_err(InjectError::Io)
+}
+
+/****************************************************************************
+ * For --dry-run
+ ****************************************************************************/
+// A unified diff of the block with a single hunk, empty if nothing changed
+struct Diff<'a> {
+    path: &'a str,
+    old: &'a str,
+    new: &'a str,
+    row: usize,
+}
+
+impl fmt::Display for Diff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.old == self.new {
+            return Ok(());
+        }
+        let old = self.old.lines().collect::<Vec<_>>();
+        let new = self.new.lines().collect::<Vec<_>>();
+
+        // Longest common subsequence, 'lcs[i][j]' is for 'old[i..]' and 'new[j..]'
+        let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                lcs[i][j] = if old[i] == new[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+
+        writeln!(f, "--- {}\n+++ {}", self.path, self.path)?;
+        writeln!(f, "@@ -{},{} +{},{} @@", self.row, old.len(), self.row, new.len())?;
+        let (mut i, mut j) = (0, 0);
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old[i] == new[j] {
+                writeln!(f, " {}", old[i])?;
+                i += 1;
+                j += 1;
+            } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+                writeln!(f, "-{}", old[i])?;
+                i += 1;
+            } else {
+                writeln!(f, "+{}", new[j])?;
+                j += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn markers_are_checked() {
+    let config = format!("a\n{}\nold\n{}\nb\n", BEGIN_MARKER, CLOSE_MARKER);
+    let (begin, close, row) = find_block(&config).unwrap();
+    assert_eq!((&config[begin..close], row), ("old\n", 3));
+
+    let missing = format!("{}\n", BEGIN_MARKER);
+    assert!(matches!(find_block(&missing), Err(InjectError::Missing(CLOSE_MARKER))));
+    let duplicate = format!("{}\n{}\n{}\n", BEGIN_MARKER, CLOSE_MARKER, BEGIN_MARKER);
+    assert!(matches!(find_block(&duplicate), Err(InjectError::Duplicate(_, 1, 3))));
+    let reversed = format!("{}\n{}\n", CLOSE_MARKER, BEGIN_MARKER);
+    assert!(matches!(find_block(&reversed), Err(InjectError::OutOfOrder)));
+}
+
+#[test]
+fn dry_run_diff() {
+    let diff = Diff { path: "config", old: "a\nb\nc\n", new: "a\nc\nd\n", row: 5 };
+    assert_eq!(
+        diff.to_string(),
+        "--- config\n+++ config\n@@ -5,3 +5,3 @@\n a\n-b\n c\n+d\n"
+    );
+}
+
+// An empty directory for a test to write into
+#[cfg(test)]
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("chordscript-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(unix)]
+#[test]
+fn writes_through_links() {
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    let dir = test_dir("links");
+    let (config, link) = (dir.join("config"), dir.join("link"));
+    fs::write(&config, "old").unwrap();
+    fs::set_permissions(&config, fs::Permissions::from_mode(0o640)).unwrap();
+    symlink("config", &link).unwrap();
+
+    write_atomic(link.to_str().unwrap(), "new").unwrap();
+    assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+    assert_eq!(fs::read_to_string(&config).unwrap(), "new");
+    assert_eq!(fs::metadata(&config).unwrap().permissions().mode() & 0o777, 0o640);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2, "The temporary file is left over");
+
+    // A link to a file that does not exist yet
+    symlink("later", dir.join("dangling")).unwrap();
+    write_atomic(dir.join("dangling").to_str().unwrap(), "new").unwrap();
+    assert_eq!(fs::read_to_string(dir.join("later")).unwrap(), "new");
+    fs::remove_dir_all(&dir).unwrap();
+}
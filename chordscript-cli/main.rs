@@ -1,6 +1,12 @@
 use std::fs;
-use chordscript::{Format, FormatError};
-use chordscript::parser::parse_to_shortcuts;
+use std::io::IsTerminal;
+use chordscript::{explain, frameworks, ErrorKind, Fallback, Format, FormatError, JsonReport, MarkupError, OutputType};
+use chordscript::parser::conditions::Profile;
+use chordscript::parser::includes::Sources;
+use chordscript::parser::shortcuts::ShortcutOwner;
+
+mod inject;
+mod watch;
 
 mod flags {
     #![allow(unused)]
@@ -9,28 +15,96 @@ mod flags {
         cmd chordscript-cli
 
         {
+            /// Print errors in the shortcuts file as a JSON array (to STDERR)
+            optional --json
+            /// The host for '@if host=...' (default is from /etc/hostname)
+            optional --host name: String
+            /// Do not report errors with {code}, e.g. CS0033 for unused variables. Only errors that leave the shortcuts intact can be allowed
+            repeated --allow code: String
+
             /// Print this help menu
             cmd help {}
 
             /// Print supported frameworks
             cmd frameworks {}
 
-            /// This is equivalent to `chordscript-cli native shell`
+            /// Print the long explanation for an error code, e.g. CS0017
+            cmd explain
+                required code: String
+            {}
+
+            /// This is equivalent to `chordscript-cli native shell`. A {filepath} of '-' reads STDIN
             cmd shell
                 required filepath: String
-            {}
+            {
+                /// Write to {path} (replaced atomically) instead of STDOUT
+                optional -o, --output path: String
+                /// For unknown hotkeys: notify-send, logger, stderr (default), none, or a command given the message
+                optional --fallback fallback: String
+                /// The framework that calls this runner, for '@if format=...' (default is shell)
+                optional --format framework: String
+            }
 
-            /// Use `chordscript-cli shell` to generate the runner. This is referenced by {runner_cmd}
+            /// Use `chordscript-cli shell --format {framework}` to generate the runner. This is referenced by {runner_cmd}
             cmd shellrunner
                 required framework: String
                 required runner_cmd: String
                 required filepath: String
-            {}
+            {
+                /// Write to {path} (replaced atomically) instead of STDOUT
+                optional -o, --output path: String
+            }
 
             cmd native
                 required framework: String
                 required filepath: String
-            {}
+            {
+                /// Write to {path} (replaced atomically) instead of STDOUT
+                optional -o, --output path: String
+            }
+
+            /// Replace the text between the markers in {target} (e.g. the i3 config) with the output, keeping a backup in {target}.bak
+            cmd inject
+                required target: String
+                required framework: String
+                required filepath: String
+            {
+                /// Use the shell runner template, referenced by {runner_cmd}
+                optional --runner runner_cmd: String
+                /// Print a diff of the generated block instead of writing it
+                optional --dry-run
+            }
+
+            /// Write the shell runner to {runner_cmd} (as an executable) and the {framework} config that calls it to {config}
+            cmd write
+                required framework: String
+                required runner_cmd: String
+                required config: String
+                required filepath: String
+            {
+                /// Only replace the text between the markers in {config}, see `inject`
+                optional --inject
+                /// For unknown hotkeys: notify-send, logger, stderr (default), none, or a command given the message
+                optional --fallback fallback: String
+            }
+
+            /// Regenerate {output} every time {filepath} changes, printing the errors when it does not parse
+            cmd watch
+                required framework: String
+                required filepath: String
+                required output: String
+            {
+                /// Also write the shell runner to {runner_cmd}, which {output} calls, as `write` does
+                optional --runner runner_cmd: String
+                /// For unknown hotkeys in the runner: notify-send, logger, stderr (default), none, or a command given the message
+                optional --fallback fallback: String
+                /// Only replace the text between the markers in {output}, see `inject`
+                optional --inject
+                /// Run {command} with `sh -c` after every write, e.g. 'i3-msg reload'
+                optional --reload command: String
+                /// How often to check {filepath}, in milliseconds (default 500)
+                optional --interval ms: u64
+            }
 
         }
     }
@@ -39,63 +113,139 @@ mod flags {
 
 //run: cargo run shellrunner i3 a ~/.config/rc/wm-shortcuts
 fn main() {
-    match flags::ChordscriptCli::from_env() {
+    match parse_args(std::env::args_os().skip(1).collect()) {
         Ok(args) => match &args.subcommand {
             flags::ChordscriptCliCmd::Help(_) => {
                 eprintln!("{}", flags::ChordscriptCli::HELP);
                 std::process::exit(1)
             }
-            flags::ChordscriptCliCmd::Frameworks(_) => {}
+            flags::ChordscriptCliCmd::Frameworks(_) => {
+                println!("{:<16} {:<6} {:<32} DESCRIPTION", "NAME", "TYPE", "FEATURES");
+                for framework in frameworks() {
+                    let features = framework.features().collect::<Vec<_>>().join(", ");
+                    let kind = if framework.is_native { "native" } else { "shell" };
+                    println!("{:<16} {:<6} {:<32} {}", framework.name, kind, features, framework.description);
+                }
+            }
+            flags::ChordscriptCliCmd::Explain(params) => match explain(&params.code) {
+                Some((message, explanation)) => {
+                    println!("{}: {}\n\n{}", params.code.to_uppercase(), message, explanation)
+                }
+                None => {
+                    eprintln!("No error with the code {:?}", params.code);
+                    std::process::exit(1)
+                }
+            },
+
+            flags::ChordscriptCliCmd::Watch(params) => {
+                let framework = params.framework.as_str();
+                let allow = allowed_errors(&args.allow);
+                match Format::from_str(framework, params.runner.as_deref()) {
+                    Ok(format) => watch::watch(params, &format, &profile(&args, framework), args.json, &allow),
+                    Err(err) => {
+                        report_format_error(args.json, &params.filepath, framework, err);
+                        std::process::exit(1);
+                    }
+                }
+            }
 
             _ => {
-                let (framework, format, filepath) = match &args.subcommand {
+                let (framework, format, filepath, output) = match &args.subcommand {
                     flags::ChordscriptCliCmd::Shell(params) => {
-                        ("shell", Format::from_str("shell", None), params.filepath.as_str())
+                        let format = Format::from_str("shell", None).map(|format| Format {
+                            fallback: params.fallback.as_deref().map_or(format.fallback, Fallback::from_name),
+                            ..format
+                        });
+                        ("shell", format, params.filepath.as_str(), params.output.as_deref())
                     }
                     flags::ChordscriptCliCmd::Native(params) => {
                         let s = params.framework.as_str();
-                        (s, Format::from_str(s, None), params.filepath.as_str())
+                        (s, Format::from_str(s, None), params.filepath.as_str(), params.output.as_deref())
                     }
                     flags::ChordscriptCliCmd::Shellrunner(params) => {
                         let s = params.framework.as_str();
-                        (s, Format::from_str(s, Some(&params.runner_cmd)), params.filepath.as_str())
+                        let format = Format::from_str(s, Some(&params.runner_cmd));
+                        (s, format, params.filepath.as_str(), params.output.as_deref())
+                    }
+                    flags::ChordscriptCliCmd::Inject(params) => {
+                        let s = params.framework.as_str();
+                        (s, Format::from_str(s, params.runner.as_deref()), params.filepath.as_str(), None)
+                    }
+                    flags::ChordscriptCliCmd::Write(params) => {
+                        let s = params.framework.as_str();
+                        (s, Format::from_str(s, Some(&params.runner_cmd)), params.filepath.as_str(), None)
                     }
 
                     // exhausitive listing
                     flags::ChordscriptCliCmd::Help(_) => unreachable!(),
                     flags::ChordscriptCliCmd::Frameworks(_) => unreachable!(),
+                    flags::ChordscriptCliCmd::Explain(_) => unreachable!(),
+                    flags::ChordscriptCliCmd::Watch(_) => unreachable!(),
                 };
 
-                let format = format.map_err(|err| match err {
-
-                    FormatError::Invalid => format!("No filetype named that {:?}", framework),
-                    FormatError::NativeUnsupported => format!("Native runner is unsupported. Did you want a shell runner:\n   chordscript shellrunner {}", framework),
-                    FormatError::ShellUnsupported => format!("Shell runner is unsupported. Did you want a native runner:\n   chordscript native {}", framework),
-                });
                 let format = match format {
                     Ok(a) => a,
                     Err(err) => {
-                        eprintln!("{}", err);
+                        report_format_error(args.json, filepath, framework, err);
                         std::process::exit(1);
                     }
 
                 };
 
 
-                let shortcutrc = match fs::read_to_string(filepath) {
-                    Ok(a) => a,
-                    Err(err) => {
-                        eprintln!("Could not read file {:?}\n{}", filepath, err);
-                        std::process::exit(1);
-                    }
+                let profile = match &args.subcommand {
+                    flags::ChordscriptCliCmd::Shell(params) => profile(&args, params.format.as_deref().unwrap_or(framework)),
+                    _ => profile(&args, framework),
                 };
-
-                let ast = parse_to_shortcuts(&shortcutrc).unwrap_or_else(|err| {
-                    println!("{:?}", err);
+                let allow = allowed_errors(&args.allow);
+                let sources = load_shortcuts(filepath, &profile, args.json).unwrap_or_else(|| std::process::exit(1));
+                let ast = sources.parse_recover(&allow).unwrap_or_else(|errors| {
+                    report_errors(args.json, filepath, &errors);
                     std::process::exit(1)
                 });
 
-                format.pipe_stdout(&ast, &mut std::io::stdout());
+                let result = match &args.subcommand {
+                    flags::ChordscriptCliCmd::Inject(params) => {
+                        format.pipe_to_string(&ast).map(|generated| {
+                            if let Err(err) = inject::inject(&params.target, &generated, params.dry_run, true) {
+                                let message = format!("Could not inject into {:?}\n{}", params.target, err);
+                                report_message(args.json, filepath, ErrorKind::OutputUnwritable, &message);
+                                std::process::exit(1);
+                            }
+                        })
+                    }
+                    flags::ChordscriptCliCmd::Write(params) => {
+                        generate_runner_and_config(&ast, &format, params.fallback.as_deref()).map(|(runner, config)| {
+                            let written =
+                                write_runner_and_config(&params.runner_cmd, &params.config, params.inject, true, &runner, &config);
+                            if let Err(err) = written {
+                                report_message(args.json, filepath, ErrorKind::OutputUnwritable, &err);
+                                std::process::exit(1);
+                            }
+                        })
+                    }
+                    _ => match output {
+                        Some(path) => format.pipe_to_string(&ast).map(|text| {
+                            if let Err(err) = inject::write_atomic(path, &text) {
+                                let message = format!("Could not write {:?}\n{}", path, err);
+                                report_message(args.json, filepath, ErrorKind::OutputUnwritable, &message);
+                                std::process::exit(1);
+                            }
+                        }),
+                        None => format.pipe_stdout(&ast, &mut std::io::stdout()),
+                    },
+                };
+                match result {
+                    Ok(()) => {}
+                    Err(FormatError::Unsupported(err)) => {
+                        report_errors(args.json, filepath, &[err.with_source_map(&sources.source_map)]);
+                        std::process::exit(1);
+                    }
+                    Err(err) => {
+                        report_format_error(args.json, filepath, framework, err);
+                        std::process::exit(1);
+                    }
+                }
 
                 //match runner {
                 //    Ok(Runner::Native(format @ Templates::ShellScript)
@@ -116,3 +266,210 @@ fn main() {
         }
     }
 }
+
+// Exits for codes that do not exist or stop the shortcuts being generated
+fn allowed_errors(codes: &[String]) -> Vec<ErrorKind> {
+    codes
+        .iter()
+        .map(|code| match ErrorKind::allowable_from_code(code) {
+            Some(kind) => kind,
+            None if explain(code).is_some() => {
+                eprintln!("{} cannot be allowed, the shortcuts would not be generated", code.to_uppercase());
+                std::process::exit(1)
+            }
+            None => {
+                eprintln!("No error with the code {:?}", code);
+                std::process::exit(1)
+            }
+        })
+        .collect()
+}
+
+const STDIN_PATH: &str = "/dev/stdin";
+
+// xflags reads every argument that starts with '-' as a flag, so the first
+// parse numbers each '-' to find the one that is {filepath}. Only that one
+// becomes STDIN, any other '-' is left for xflags to reject
+fn parse_args(args: Vec<std::ffi::OsString>) -> xflags::Result<flags::ChordscriptCli> {
+    // Arguments from the OS cannot contain a null byte
+    let numbered = args
+        .iter()
+        .enumerate()
+        .map(|(i, arg)| if arg == "-" { format!("\0{}", i).into() } else { arg.clone() })
+        .collect();
+    let stdin_index = flags::ChordscriptCli::from_vec(numbered)
+        .ok()
+        .and_then(|parsed| filepath(&parsed)?.strip_prefix('\0')?.parse::<usize>().ok());
+    let args = args
+        .into_iter()
+        .enumerate()
+        .map(|(i, arg)| if Some(i) == stdin_index { STDIN_PATH.into() } else { arg })
+        .collect();
+    flags::ChordscriptCli::from_vec(args)
+}
+
+fn filepath(args: &flags::ChordscriptCli) -> Option<&str> {
+    match &args.subcommand {
+        flags::ChordscriptCliCmd::Help(_) | flags::ChordscriptCliCmd::Frameworks(_) => None,
+        flags::ChordscriptCliCmd::Explain(_) => None,
+        flags::ChordscriptCliCmd::Shell(params) => Some(&params.filepath),
+        flags::ChordscriptCliCmd::Shellrunner(params) => Some(&params.filepath),
+        flags::ChordscriptCliCmd::Native(params) => Some(&params.filepath),
+        flags::ChordscriptCliCmd::Inject(params) => Some(&params.filepath),
+        flags::ChordscriptCliCmd::Write(params) => Some(&params.filepath),
+        flags::ChordscriptCliCmd::Watch(params) => Some(&params.filepath),
+    }
+}
+
+// '-' is STDIN, for pipelines
+fn read_shortcuts(filepath: &str) -> std::io::Result<String> {
+    if filepath == STDIN_PATH {
+        std::io::read_to_string(std::io::stdin())
+    } else {
+        fs::read_to_string(filepath)
+    }
+}
+
+// For '@if', the format is the framework even for the shell runner so that
+// the commands can be specific to it
+fn profile<'a>(args: &'a flags::ChordscriptCli, framework: &'a str) -> Profile<'a> {
+    Profile {
+        host: args.host.as_deref().or_else(|| HOSTNAME.get_or_init(read_hostname).as_deref()),
+        format: Some(framework),
+    }
+}
+
+static HOSTNAME: std::sync::OnceLock<Option<String>> = std::sync::OnceLock::new();
+
+fn read_hostname() -> Option<String> {
+    let hostname = fs::read_to_string("/etc/hostname").ok()?;
+    Some(hostname.trim().to_string()).filter(|hostname| !hostname.is_empty())
+}
+
+// Along with every file it includes, printing why if it cannot
+fn load_shortcuts(filepath: &str, profile: &Profile, is_json: bool) -> Option<Sources> {
+    let shortcutrc = match read_shortcuts(filepath) {
+        Ok(a) => a,
+        Err(err) => {
+            let message = format!("Could not read file {:?}\n{}", filepath, err);
+            report_message(is_json, filepath, ErrorKind::FileUnreadable, &message);
+            return None;
+        }
+    };
+    // So that includes in STDIN are relative to the current directory
+    let root = if filepath == STDIN_PATH { "-" } else { filepath };
+    Sources::load(root, &shortcutrc, profile, |path| fs::read_to_string(path))
+        .map_err(|err| report_errors(is_json, filepath, &[err]))
+        .ok()
+}
+
+// Both are generated before writing either so they stay in sync
+fn generate_runner_and_config(
+    ast: &ShortcutOwner,
+    format: &Format,
+    fallback: Option<&str>,
+) -> Result<(String, String), FormatError> {
+    let shell = Format::from_str("shell", None)?;
+    let shell = Format {
+        fallback: fallback.map_or(shell.fallback, Fallback::from_name),
+        ..shell
+    };
+    Ok((shell.pipe_to_string(ast)?, format.pipe_to_string(ast)?))
+}
+
+// 'is_backup' is for 'is_inject', see 'inject::inject()'
+fn write_runner_and_config(
+    runner_path: &str,
+    config_path: &str,
+    is_inject: bool,
+    is_backup: bool,
+    runner: &str,
+    config: &str,
+) -> Result<(), String> {
+    inject::write_atomic(runner_path, runner)
+        .and_then(|()| set_executable(runner_path))
+        .map_err(|err| format!("Could not write the runner {:?}\n{}", runner_path, err))?;
+
+    if is_inject {
+        inject::inject(config_path, config, false, is_backup)
+            .map_err(|err| format!("Could not inject into {:?}\n{}", config_path, err))
+    } else {
+        inject::write_atomic(config_path, config)
+            .map_err(|err| format!("Could not write the config {:?}\n{}", config_path, err))
+    }
+}
+
+// The same as `chmod +x`, executable for whoever can read it
+#[cfg(unix)]
+fn set_executable(path: &str) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut permissions = fs::metadata(path)?.permissions();
+    let mode = permissions.mode();
+    permissions.set_mode(mode | ((mode & 0o444) >> 2));
+    fs::set_permissions(path, permissions)
+}
+#[cfg(not(unix))]
+fn set_executable(_: &str) -> std::io::Result<()> {
+    Ok(())
+}
+
+fn report_errors(is_json: bool, filepath: &str, errors: &[MarkupError]) {
+    if is_json {
+        eprintln!("{}", JsonReport { path: filepath, errors });
+    } else {
+        let output = stderr_output_type();
+        errors.iter().for_each(|err| eprintln!("{}", err.render(output)));
+    }
+}
+
+// Colour only for a terminal, and never when NO_COLOR is set (no-color.org)
+fn stderr_output_type() -> OutputType {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    if !no_color && std::io::stderr().is_terminal() {
+        OutputType::PosixShell
+    } else {
+        OutputType::Plain
+    }
+}
+
+// For errors without any text in the shortcuts file to point at, e.g. a
+// file that cannot be read. The JSON report has an empty span.
+fn report_message(is_json: bool, filepath: &str, kind: ErrorKind, message: &str) {
+    if is_json {
+        let empty = "";
+        let error = MarkupError::from_str(empty, empty, kind).with_note(message);
+        report_errors(is_json, filepath, &[error]);
+    } else {
+        eprintln!("{}", message);
+    }
+}
+
+fn report_format_error(is_json: bool, filepath: &str, framework: &str, err: FormatError) {
+    match err {
+        FormatError::Unsupported(err) => report_errors(is_json, filepath, &[err]),
+        err => report_message(is_json, filepath, ErrorKind::FrameworkUnsupported, &format_error(framework, err)),
+    }
+}
+
+fn format_error(framework: &str, err: FormatError) -> String {
+    match err {
+        FormatError::Invalid => format!("No filetype named that {:?}", framework),
+        FormatError::NativeUnsupported => format!("Native runner is unsupported. Did you want a shell runner:\n   chordscript shellrunner {}", framework),
+        FormatError::ShellUnsupported => format!("Shell runner is unsupported. Did you want a native runner:\n   chordscript native {}", framework),
+        FormatError::Unsupported(err) => format!("{}", err),
+    }
+}
+
+#[test]
+fn only_the_filepath_dash_is_stdin() {
+    let parse = |args: &[&str]| parse_args(args.iter().map(Into::into).collect());
+    let args = parse(&["--json", "--host", "a", "shell", "-o", "out", "-"]).unwrap();
+    assert_eq!((args.json, args.host.as_deref(), filepath(&args)), (true, Some("a"), Some(STDIN_PATH)));
+    let args = parse(&["inject", "config", "i3", "-"]).unwrap();
+    assert_eq!(filepath(&args), Some(STDIN_PATH));
+
+    let err = parse(&["shell", "--output", "-", "-"]).unwrap_err();
+    assert_eq!(err.to_string(), "expected a value for `--output`");
+    let err = parse(&["--allow", "-", "native", "i3", "rc"]).unwrap_err();
+    assert_eq!(err.to_string(), "expected a value for `--allow`");
+}
@@ -0,0 +1,168 @@
+// Regenerates the output every time the shortcuts file (or a file it
+// includes) is saved. This polls the modified time so it works the same
+// everywhere (no inotify).
+
+use std::{fs, process::Command, thread, time::Duration, time::SystemTime};
+
+use chordscript::parser::conditions::Profile;
+use chordscript::{ErrorKind, Format, FormatError};
+
+use crate::{
+    flags, generate_runner_and_config, inject, load_shortcuts, report_errors, report_format_error, report_message,
+    write_runner_and_config,
+};
+
+const DEFAULT_INTERVAL_MS: u64 = 500;
+
+pub fn watch(params: &flags::Watch, format: &Format, profile: &Profile, is_json: bool, allow: &[ErrorKind]) -> ! {
+    let interval = Duration::from_millis(params.interval.unwrap_or(DEFAULT_INTERVAL_MS));
+    // Once, so the backup is the config from before watching rather than the
+    // last regeneration
+    if params.inject {
+        if let Err(err) = inject::backup(&params.output) {
+            let message = format!("Could not back up {:?}\n{}", params.output, err);
+            report_message(is_json, &params.filepath, ErrorKind::OutputUnwritable, &message);
+            std::process::exit(1);
+        }
+    }
+    let mut files = vec![params.filepath.clone()];
+    let mut last_modified = Vec::new();
+    loop {
+        let modified = files.iter().map(|file| modified_time(file)).collect::<Vec<_>>();
+        if modified[0].is_some() && modified != last_modified {
+            let before = files.clone();
+            if regenerate(params, format, profile, is_json, allow, &mut files) {
+                reload(params);
+            }
+            // Files that are newly included have to be timed now, otherwise the
+            // next poll sees them as changed and regenerates again
+            last_modified = files
+                .iter()
+                .map(|file| match before.iter().position(|b| b == file) {
+                    Some(i) => modified[i],
+                    None => modified_time(file),
+                })
+                .collect();
+        }
+        thread::sleep(interval);
+    }
+}
+
+// Editors that save by renaming can leave the file missing briefly
+fn modified_time(file: &str) -> Option<SystemTime> {
+    fs::metadata(file).and_then(|m| m.modified()).ok()
+}
+
+// Only writes the output if every step succeeds, returns if it was written.
+// 'files' is updated with the files that are included.
+fn regenerate(
+    params: &flags::Watch,
+    format: &Format,
+    profile: &Profile,
+    is_json: bool,
+    allow: &[ErrorKind],
+    files: &mut Vec<String>,
+) -> bool {
+    let filepath = params.filepath.as_str();
+    let sources = match load_shortcuts(filepath, profile, is_json) {
+        Some(a) => a,
+        None => return false,
+    };
+    files.splice(1.., sources.source_map.files().skip(1).map(String::from));
+
+    let ast = match sources.parse_recover(allow) {
+        Ok(a) => a,
+        Err(errors) => {
+            report_errors(is_json, filepath, &errors);
+            return false;
+        }
+    };
+    // The runner holds the commands, so it is written along with the config
+    let generated = match &params.runner {
+        Some(_) => generate_runner_and_config(&ast, format, params.fallback.as_deref()).map(|(r, c)| (Some(r), c)),
+        None => format.pipe_to_string(&ast).map(|config| (None, config)),
+    };
+    let (runner, config) = match generated {
+        Ok(a) => a,
+        Err(FormatError::Unsupported(err)) => {
+            report_errors(is_json, filepath, &[err.with_source_map(&sources.source_map)]);
+            return false;
+        }
+        Err(err) => {
+            report_format_error(is_json, filepath, &params.framework, err);
+            return false;
+        }
+    };
+
+    let output = params.output.as_str();
+    let written = match (&params.runner, runner) {
+        (Some(runner_path), Some(runner)) => {
+            write_runner_and_config(runner_path, output, params.inject, false, &runner, &config)
+                .map(|()| eprintln!("Wrote {:?} and {:?}", runner_path, output))
+        }
+        _ => if params.inject {
+            inject::inject(output, &config, false, false).map_err(|err| err.to_string())
+        } else {
+            inject::write_atomic(output, &config).map_err(|err| err.to_string())
+        }
+        .map(|()| eprintln!("Wrote {:?}", output))
+        .map_err(|err| format!("Could not write {:?}\n{}", output, err)),
+    };
+    match written {
+        Ok(()) => true,
+        Err(err) => {
+            report_message(is_json, filepath, ErrorKind::OutputUnwritable, &err);
+            false
+        }
+    }
+}
+
+fn reload(params: &flags::Watch) {
+    if let Some(command) = &params.reload {
+        match Command::new("sh").arg("-c").arg(command).status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => eprintln!("Reload command {:?} failed with {}", command, status),
+            Err(err) => eprintln!("Could not run the reload command {:?}\n{}", command, err),
+        }
+    }
+}
+
+#[test]
+fn regenerates_only_valid_files() {
+    use crate::inject::{test_dir, BEGIN_MARKER, CLOSE_MARKER};
+
+    let dir = test_dir("watch");
+    let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+    let mut params = flags::Watch {
+        framework: "i3".to_string(),
+        filepath: path("wm-shortcuts"),
+        output: path("config"),
+        runner: None,
+        fallback: None,
+        inject: false,
+        reload: None,
+        interval: None,
+    };
+    let format = Format::from_str("i3", None).unwrap();
+    let profile = Profile::default();
+    let regenerate = |params: &flags::Watch| regenerate(params, &format, &profile, false, &[], &mut vec![params.filepath.clone()]);
+
+    fs::write(&params.filepath, "|super a| echo a\n").unwrap();
+    assert!(regenerate(&params));
+    assert!(fs::read_to_string(&params.output).unwrap().contains("bindsym Mod4+a exec --no-startup-id \"echo a\""));
+
+    // The output is left as it was
+    fs::write(&params.filepath, "|super a| echo a\n|super a| echo again\n").unwrap();
+    let before = fs::read_to_string(&params.output).unwrap();
+    assert!(!regenerate(&params));
+    assert_eq!(fs::read_to_string(&params.output).unwrap(), before);
+
+    // Only 'watch()' makes a backup, once
+    params.inject = true;
+    fs::write(&params.output, format!("hand\n{}\n{}\n", BEGIN_MARKER, CLOSE_MARKER)).unwrap();
+    fs::write(&params.filepath, "|super b| echo b\n").unwrap();
+    assert!(regenerate(&params));
+    assert!(fs::read_to_string(&params.output).unwrap().starts_with("hand\n"));
+    assert!(fs::metadata(format!("{}.bak", params.output)).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
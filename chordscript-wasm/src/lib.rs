@@ -1,19 +1,36 @@
 use wasm_bindgen::prelude::*;
 
 //use chordscript::templates::{PreallocPush, Templates};
-use chordscript::parser::parse_to_shortcuts;
-use chordscript::Format;
+use chordscript::parser::{conditions::Profile, includes::Sources};
+use chordscript::{frameworks, Fallback, Format, FormatError, MarkupError, OutputType};
 
 
+fn render(errors: &[MarkupError]) -> String {
+    errors.iter().map(|err| err.render(OutputType::Html)).collect::<Vec<_>>().join("\n")
+}
+
 //run: ../make.sh
 #[wasm_bindgen]
-pub fn parse(a: String, format_id: usize) -> Result<String, String> {
-    let owner = parse_to_shortcuts(&a).map_err(|err| format!("{}", err))?;
+pub fn parse(a: String, format_id: usize, runner: String) -> Result<String, String> {
+    // '@if format=...' matches the framework that is selected
+    let profile = Profile {
+        host: None,
+        format: frameworks().nth(format_id).map(|framework| framework.name),
+    };
+    let sources = Sources::load("shortcuts", &a, &profile, |_| {
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "The playground cannot read files"))
+    })
+    .map_err(|err| render(&[err]))?;
+    let owner = sources.parse_recover(&[]).map_err(|errors| render(&errors))?;
 
     let format = Format {
         id: format_id,
-        runner: "<shortcuts.sh>",
+        runner: &runner,
+        fallback: Fallback::Stderr,
     };
 
-    Ok(format.pipe_to_string(&owner))
+    format.pipe_to_string(&owner).map_err(|err| match err {
+        FormatError::Unsupported(err) => render(&[err.with_source_map(&sources.source_map)]),
+        err => format!("{:?}", err),
+    })
 }
@@ -36,7 +36,7 @@ macro_rules! const_join_str {
         };
 
         const $raw: [u8; $len] = {
-            let mut temp = [' ' as u8; $len];
+            let mut temp = [b' '; $len];
             let mut i = 0;
             let mut len = 0;
             loop {
@@ -60,7 +60,7 @@ macro_rules! const_join_str {
 // Constants
 ////////////////////////////////////////////////////////////////////////////////
 
-pub const VALID_ESCAPEES: [&str; 5] = ["\\", "|", ",", "n", "\\n"];
+pub const VALID_ESCAPEES: [&str; 6] = ["\\", "|", ",", "@", "n", "\\n"];
 const_join_str!(pub const VALID_ESCAPEE_STR: &str = VALID_ESCAPEES | join(A_RAW A_LEN));
 
 // https://www.unicode.org/Public/UCD/latest/ucd/PropList.txt
@@ -86,7 +86,6 @@ array_index_by_enum! { MODIFIER_COUNT: usize
         Alt => "alt", Ctrl => "ctrl", Shift => "shift", Super => "super",
     } => 1 pub const MODIFIERS: [&str]
 }
-pub const MOD_UTF8_MAX_LEN: usize = fold_max_len(&MODIFIERS);
 
 array_index_by_enum! { KEYCODE_COUNT: usize
     pub enum Keycodes {
@@ -110,7 +109,6 @@ array_index_by_enum! { KEYCODE_COUNT: usize
         XF86MonBrightnessDown => "XF86MonBrightnessDown",
     } => 1 pub const KEYCODES: [&str]
 }
-pub const KEY_UTF8_MAX_LEN: usize = fold_max_len(&KEYCODES);
 
 const ALL_KEYS: [&str; MODIFIERS.len() + KEYCODES.len()] = {
     let mut base = [""; MODIFIERS.len() + KEYCODES.len()];
@@ -121,17 +119,14 @@ const ALL_KEYS: [&str; MODIFIERS.len() + KEYCODES.len()] = {
 // Join ALL_KEYS for printing an error message
 const_join_str!(pub const AVAILABLE_KEYS: &str = ALL_KEYS | join(JOIN_RAW JOIN_LEN));
 
-const fn fold_max_len(list: &[&str]) -> usize {
+pub const fn fold_max_len(list: &[&str]) -> usize {
     let mut max_len = 0;
     let mut i = 0;
-    loop {
+    while i < list.len() {
         if max_len < list[i].len() {
             max_len = list[i].len();
         }
         i += 1;
-        if i >= list.len() {
-            break;
-        }
     }
     max_len
 }
@@ -167,7 +162,7 @@ mod test {
     #[test]
     fn keycodes_is_sorted() {
         // Check if KEYCODES is sorted
-        let mut sorted = KEYCODES.clone();
+        let mut sorted = KEYCODES;
         sorted.sort_by(|a, b| {
             if a.len() == b.len() {
                 a.cmp(b)
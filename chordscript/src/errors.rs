@@ -1,4 +1,5 @@
 //use crate::constants::AVAILABLE_KEYS;
+use crate::array_index_by_enum;
 
 //run: cargo test -- --nocapture
 
@@ -78,7 +79,8 @@ pub mod lexer {
     pub const INVALID_LINE_START: &str = "Valid starting characters for a line are:\n\
         - '#' (comments),\n\
         - '!' (placeholders),\n\
-        - '|' (commands)";
+        - '|' (commands),\n\
+        - '@let' (variables)";
 
     pub const EXCLAIM_IN_HEAD: &str =
         "You are currently defining a head, not a placeholder.  Did you mean to use '|' instead?";
@@ -96,7 +98,6 @@ pub mod lexer {
         Valid escapes are: " => crate::constants::VALID_ESCAPEE_STR);
 
     //pub const HEAD_COMMA_OUTSIDE_BRACKETS: &str = "Unexpected comma ','. Type 'comma' for the key, ';' for a chord separator. ',' only has meaning inside an enumeration group '{{..}}'.";
-    //pub const UNFINISHED_LITERAL: &str = "Missing '}}}' to close the literal text input.";
     //pub const UNFINISHED_BRACKETS: &str = "Missing '}}' to close the permutations bracket.";
     pub const MISSING_LBRACKET: &str =
         "Missing a second opening curly brace. Need '{{' to start an enumeration";
@@ -112,16 +113,43 @@ pub mod lexer {
     pub const DOUBLE_LBRACKET_IN_BODY_PERMUTATION_GROUP: &str = "You cannot have '{{' inside a permutation group. Either you forgot to close the previous permutation group or you need to escape it like '\\{\\{'.";
 
     pub const END_BEFORE_HEAD_CLOSE: &str = "You did not close the head. Please add a '|'. Alternatively, if you placed '|' intentionally at the start of a line, you may wish to consider the following:\n\
-        - '{|\\||}' (literals)\n\
+        - '{{{|}}}' (literals)\n\
         - '{{\\|}}' (you have to add to each relevant permutation), or\n\
         - '{{|}}' (not necessary to escape the backslash)\n\
         depending on your use case.";
+    pub const BRACKET_OPENED: &str = "enumeration opened here";
+    pub const END_BEFORE_LITERAL_CLOSE: &str = "Missing '}}}' to close the literal text.";
+    pub const LITERAL_OPENED: &str = "literal opened here";
+    pub const NO_ENTRIES: &str =
+        "There are no shortcuts in this file, only comments or definitions. Add one like '|super a| command'.";
     pub const END_BEFORE_PLACEHOLDER_CLOSE: &str =
         "You did not close the placehoder head. Please add a '!'.";
     pub const END_BEFORE_BRACKET_CLOSE: &str = "\
         Missing a second closing curly brace to close the permutation group. \
         Need '}}' to close. If you want a '}' as output, escape it with backslash \
         like '\\}'.";
+
+    pub const INVALID_DEFINITION: &str =
+        "Expected a variable definition like '@let name = value'. Names are made of letters, digits and '_'.";
+    pub const UNDEFINED_VARIABLE: &str =
+        "This variable is not defined. Define it on a line before with '@let name = value', \
+        or write '\\@{name}' to keep it as is.";
+    pub const UNUSED_VARIABLE: &str =
+        "This variable is never used. Use it with '@{name}' or delete the definition.";
+    pub const REDEFINED_VARIABLE: &str = "This variable is already defined.";
+    pub const VARIABLE_FIRST_DEFINED: &str = "first defined here";
+}
+
+pub mod includes {
+    pub const INVALID_INCLUDE: &str = "Expected the path of the file to include, like '@include path'.";
+    pub const INCLUDE_UNREADABLE: &str = "Could not read the included file.";
+    pub const INCLUDE_CYCLE: &str = "This file is already being included.";
+    pub const TOO_DEEP: &str = "Files are included too many levels deep.";
+
+    pub const INVALID_CONDITION: &str =
+        "Expected a condition like '@if host=name' or '@if format=name'.";
+    pub const UNCLOSED_CONDITION: &str = "Missing an '@end' for this '@if' in the same file.";
+    pub const UNMATCHED_END: &str = "There is no '@if' for this '@end'.";
 }
 
 pub mod parser {
@@ -129,8 +157,223 @@ pub mod parser {
 
     const_concat!(const INVALID_KEY = "Not a valid key. The valid keys are the \
         following:\n    " => AVAILABLE_KEYS);
-    //pub const HOTKEY_DUPLICATE: &str = "This hotkey is defined previously.";
-    //pub const HOTKEY_UNREACHABLE: &str = "This overall hotkey is not accessible because the part of the hotkey is already defined and will be recognised first.";
+    pub const MODIFIER_DUPLICATE: &str = "Modifier already used";
+    pub const KEY_DUPLICATE: &str =
+        "Key already used. A chord has at most one key, use ';' to start the next chord.";
+    pub const ALREADY_USED: &str = "already used here";
+    pub const PERMUTATION_CLASH: &str =
+        "Every choice of an enumeration '{{..}}' is combined with the rest of the chord, \
+        so at least one of the permutations uses this twice.";
+    pub const HOTKEY_DUPLICATE: &str = "This hotkey is defined previously.";
+    pub const HOTKEY_UNREACHABLE: &str = "This overall hotkey is not accessible because the part of the hotkey is already defined and will be recognised first.";
+    pub const HOTKEY_FIRST_DEFINED: &str = "first defined here";
+    pub const HOTKEY_RECOGNISED_FIRST: &str = "recognised first";
+}
+
+pub mod templates {
+    pub const SXHKD_NEWLINE_IN_COMMAND: &str =
+        "An sxhkdrc command cannot have a newline inside of it.\n\
+        Newlines that only lay out a '{{..}}' choice are fine. Either put this command on one line, \
+        or use the shell runner for sxhkd's hotkeys.";
+    pub const LEFTWM_MULTI_CHORD: &str =
+        "LeftWM has no modes or chord chains, so it cannot bind a hotkey with more than one chord.\n\
+        Either shorten this to a single chord, make it a placeholder with '!', \
+        or run it through a hotkey daemon like sxhkd.";
+    pub const I3_NEWLINE_IN_COMMAND: &str =
+        "The i3 and sway configs cannot have a newline inside of a command.\n\
+        Either put this command on one line, or use the shell runner, i.e. \
+        `chordscript-cli shellrunner i3 ...`, to keep the command in a shellscript.";
+    pub const HYPRLAND_NEWLINE_IN_COMMAND: &str =
+        "The Hyprland config cannot have a newline inside of a command.\n\
+        Either put this command on one line, or move it into a shellscript and call that.";
+    pub const TMUX_SUPER: &str =
+        "tmux cannot see the super key, the terminal never sends it.\n\
+        Use 'ctrl', 'alt', or 'shift' instead, or make it a placeholder with '!'.";
+    pub const TMUX_UNKNOWN_KEY: &str =
+        "tmux has no name for this key, the terminal never sends it.";
+    pub const TMUX_NEWLINE_IN_COMMAND: &str =
+        "A tmux 'bind-key' cannot have a newline inside of a command.\n\
+        Either put this command on one line, or move it into a shellscript and call that.";
+    pub const FRAMEWORK_UNSUPPORTED: &str = "Cannot generate this framework.";
+}
+
+pub mod io {
+    pub const FILE_UNREADABLE: &str = "Could not read the shortcuts file.";
+    pub const OUTPUT_UNWRITABLE: &str = "Could not write the output.";
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Error codes
+////////////////////////////////////////////////////////////////////////////////
+// The codes are stable for users to search for. Only ever append new kinds,
+// never renumber or reuse a code.
+array_index_by_enum! { ERROR_KIND_COUNT: usize
+    pub enum ErrorKind {
+        HeadInvalidClose => "CS0001" => lexer::HEAD_INVALID_CLOSE =>
+            "A '|' inside of an enumeration '{{..}}' in the hotkey. The hotkey ends at \
+            the second '|', so the enumeration has to be closed before it.\n\
+            \n    |super {{a, b| echo    # error\n    |super {{a, b}}| echo  # ok",
+        InvalidLineStart => "CS0002" => lexer::INVALID_LINE_START =>
+            "Before the first entry, every line has to be blank, a comment or a '@let' \
+            definition. An entry starts with a '|' (a hotkey) or '!' (a placeholder) at the very start of a line.",
+        ExclaimInHead => "CS0003" => lexer::EXCLAIM_IN_HEAD =>
+            "Hotkeys start and end with '|', placeholders start and end with '!'. \
+            This hotkey was opened with '|' but closed with '!'.",
+        BarInPlaceholder => "CS0004" => lexer::BAR_IN_PLACEHOLDER =>
+            "Hotkeys start and end with '|', placeholders start and end with '!'. \
+            This placeholder was opened with '!' but closed with '|'.",
+        HeadNoEscaping => "CS0005" => lexer::HEAD_NO_ESCAPING =>
+            "Backslash escapes only exist in the command. Every key in a hotkey has a \
+            name without special characters, e.g. 'semicolon' rather than '\\;'.",
+        BodyBracketNoNewlineBar => "CS0006" => lexer::BODY_BRACKET_NO_NEWLINE_BAR =>
+            "A '|' at the start of a line always starts a new entry, even inside of an \
+            enumeration '{{..}}' in the command. Most likely the enumeration was not closed.",
+        InvalidEscape => "CS0007" => lexer::INVALID_ESCAPE =>
+            "Only a few characters can be escaped with '\\' inside of an enumeration \
+            '{{..}}' in the command. Everywhere else in the command a backslash is kept \
+            as is for the shell.",
+        MissingLBracket => "CS0008" => lexer::MISSING_LBRACKET =>
+            "Enumerations are opened with two curly braces '{{'. A single '{' has no \
+            meaning in a hotkey.",
+        MissingRBracket => "CS0009" => lexer::MISSING_RBRACKET =>
+            "Enumerations are closed with two curly braces '}}'. A single '}' has no \
+            meaning in a hotkey.",
+        EmptyHotkey => "CS0010" => lexer::EMPTY_HOTKEY =>
+            "'||' or '!!' at the start of a line is a hotkey without any keys.",
+        MoreBodyThanHeadPermutations => "CS0011" => lexer::MORE_BODY_THAN_HEAD_PERMUTATIONS =>
+            "Every choice of an enumeration in the command belongs to the hotkey with the \
+            same choice, so there cannot be more choices in the command than in the hotkey.\n\
+            \n    |super {{a, b}}| echo {{1, 2, 3}}  # error\n    |super {{a, b}}| echo {{1, 2}}     # ok",
+        DoubleLBracketInBodyPermutationGroup => "CS0012" => lexer::DOUBLE_LBRACKET_IN_BODY_PERMUTATION_GROUP =>
+            "Enumerations cannot be nested. A '{{' inside of one usually means the \
+            previous enumeration was never closed.",
+        EndBeforeHeadClose => "CS0013" => lexer::END_BEFORE_HEAD_CLOSE =>
+            "The file ended while still inside of a hotkey. Every hotkey needs a closing '|'.",
+        EndBeforePlaceholderClose => "CS0014" => lexer::END_BEFORE_PLACEHOLDER_CLOSE =>
+            "The file ended while still inside of a placeholder. Every placeholder needs a \
+            closing '!'.",
+        EndBeforeBracketClose => "CS0015" => lexer::END_BEFORE_BRACKET_CLOSE =>
+            "The file ended while still inside of an enumeration. Every '{{' needs a '}}'.",
+        InvalidKey => "CS0016" => parser::INVALID_KEY =>
+            "Keys follow the naming of xev. Modifiers are lowercase, e.g. 'super', and are \
+            separated from other keys with spaces or '+'.",
+        ModifierDuplicate => "CS0017" => parser::MODIFIER_DUPLICATE =>
+            "A chord holds each modifier at most once. With an enumeration, this can happen \
+            in just one of the permutations, e.g. 'super {{super, alt}} a'.",
+        KeyDuplicate => "CS0018" => parser::KEY_DUPLICATE =>
+            "A chord is any number of modifiers and exactly one key pressed together. \
+            Separate chords that are pressed one after another with ';'.\n\
+            \n    |super a b| echo    # error\n    |super a ; b| echo  # ok",
+        HotkeyDuplicate => "CS0019" => parser::HOTKEY_DUPLICATE =>
+            "The same hotkey is bound twice, so only one of the commands could ever run. \
+            Enumerations and the order of modifiers can hide this, e.g. 'alt super a' is \
+            one of the hotkeys produced by 'super {{alt, ctrl}} a'.",
+        HotkeyUnreachable => "CS0020" => parser::HOTKEY_UNREACHABLE =>
+            "A hotkey made of several chords is pressed one chord after another. If the \
+            first chords are already a hotkey of their own, that hotkey runs before the \
+            rest can be pressed.",
+        LeftWmMultiChord => "CS0021" => templates::LEFTWM_MULTI_CHORD =>
+            "LeftWM binds one chord to one command, there are no modes to press a second \
+            chord in.",
+        I3NewlineInCommand => "CS0022" => templates::I3_NEWLINE_IN_COMMAND =>
+            "Each 'bindsym' in an i3 or sway config is one line. The shell runner keeps \
+            the command in a shellscript where newlines are fine.",
+        HyprlandNewlineInCommand => "CS0023" => templates::HYPRLAND_NEWLINE_IN_COMMAND =>
+            "Each 'bind' in a Hyprland config is one line.",
+        TmuxSuper => "CS0024" => templates::TMUX_SUPER =>
+            "tmux only sees the keys that the terminal sends, and terminals do not send \
+            the super modifier.",
+        TmuxUnknownKey => "CS0025" => templates::TMUX_UNKNOWN_KEY =>
+            "tmux only sees the keys that the terminal sends. Keys such as 'Print' or the \
+            brightness keys are handled before they ever reach a terminal.",
+        TmuxNewlineInCommand => "CS0026" => templates::TMUX_NEWLINE_IN_COMMAND =>
+            "Each 'bind-key' in a tmux config is one line.",
+        SxhkdNewlineInCommand => "CS0027" => templates::SXHKD_NEWLINE_IN_COMMAND =>
+            "sxhkd joins a line ending in '\\' with the next one, which changes what a \
+            multi-line shellscript means, e.g. 'then' and 'fi' end up on one line.",
+        FileUnreadable => "CS0028" => io::FILE_UNREADABLE =>
+            "The shortcuts file (or STDIN for '-') could not be read at all, e.g. it does \
+            not exist or the permissions do not allow it. The note has the reason.",
+        FrameworkUnsupported => "CS0029" => templates::FRAMEWORK_UNSUPPORTED =>
+            "`chordscript-cli frameworks` lists every framework and whether it is native or \
+            goes through the shell runner. 'native' only takes native frameworks and \
+            'shellrunner' only takes shell ones. The note has which it is.",
+        OutputUnwritable => "CS0030" => io::OUTPUT_UNWRITABLE =>
+            "The file for '--output', or the runner or config of 'inject', 'write' and \
+            'watch' could not be written, e.g. the directory does not exist or the markers \
+            for 'inject' are missing. The note has the reason.",
+        InvalidDefinition => "CS0031" => lexer::INVALID_DEFINITION =>
+            "A line starting with '@let' defines a variable. The value is the rest of the \
+            line after the '=' (without the surrounding whitespace) and cannot use other \
+            variables.\n\
+            \n    @let term = alacritty -e\n    |super return| @{term} tmux",
+        UndefinedVariable => "CS0032" => lexer::UNDEFINED_VARIABLE =>
+            "'@{name}' is replaced with the value of a variable from '@let'. Variables \
+            have to be defined before they are used. In a command, a backslash keeps it as \
+            is, e.g. 'git log \\@{u}'.",
+        UnusedVariable => "CS0033" => lexer::UNUSED_VARIABLE =>
+            "Every variable defined with '@let' has to be used at least once, which \
+            catches typos in either the definition or the '@{name}'. This can be turned \
+            off with `chordscript-cli --allow CS0033`.",
+        RedefinedVariable => "CS0034" => lexer::REDEFINED_VARIABLE =>
+            "A variable can only be defined once with '@let'. Every '@{name}' uses the \
+            one value.",
+        NoEntries => "CS0035" => lexer::NO_ENTRIES =>
+            "A file needs at least one shortcut, '|hotkey| command', or placeholder, \
+            '!hotkey! command'. Comments and '@let' definitions on their own do not \
+            generate anything.",
+        InvalidInclude => "CS0036" => includes::INVALID_INCLUDE =>
+            "A line starting with '@include' is replaced with the contents of the file at \
+            the path after it. Relative paths are from the directory of the file with the \
+            '@include'.\n\
+            \n    @include shared-shortcuts",
+        IncludeUnreadable => "CS0037" => includes::INCLUDE_UNREADABLE =>
+            "Relative paths in '@include' are from the directory of the file with the \
+            '@include', not the current directory.",
+        IncludeCycle => "CS0038" => includes::INCLUDE_CYCLE =>
+            "A file that includes itself, directly or through other files, would be \
+            included forever.",
+        InvalidCondition => "CS0039" => includes::INVALID_CONDITION =>
+            "The lines between '@if' and '@end' are only kept when the condition matches. \
+            'host' is the hostname (or '--host'), 'format' is the framework being \
+            generated, e.g. 'sway'.\n\
+            \n    @if host=laptop\n    |XF86MonBrightnessUp| light -A 5\n    @end",
+        UnclosedCondition => "CS0040" => includes::UNCLOSED_CONDITION =>
+            "Every '@if' needs an '@end' after it. An '@if' in an included file has to \
+            end in that file.",
+        UnmatchedEnd => "CS0041" => includes::UNMATCHED_END =>
+            "Every '@end' closes the closest '@if' before it that is still open.",
+        EndBeforeLiteralClose => "CS0042" => lexer::END_BEFORE_LITERAL_CLOSE =>
+            "Everything after '{{{' is output as is until the '}}}', so a missing '}}}' \
+            takes in the rest of the file.",
+    }
+    => 1 pub const ERROR_CODES: [&str]
+    => 2 pub const ERROR_MESSAGES: [&str]
+    => 3 pub const ERROR_EXPLANATIONS: [&str]
+}
+
+impl ErrorKind {
+    pub const fn code(&self) -> &'static str {
+        ERROR_CODES[self.id()]
+    }
+    pub const fn message(&self) -> &'static str {
+        ERROR_MESSAGES[self.id()]
+    }
+    pub fn allowable_from_code(code: &str) -> Option<Self> {
+        ALLOWABLE.into_iter().find(|kind| kind.code().eq_ignore_ascii_case(code))
+    }
+}
+
+// Errors that leave every shortcut intact, so they can be turned off, e.g.
+// with `chordscript-cli --allow CS0033`
+pub const ALLOWABLE: [ErrorKind; 1] = [ErrorKind::UnusedVariable];
+
+// The short and long-form explanation for a code, e.g. 'CS0001'
+pub fn explain(code: &str) -> Option<(&'static str, &'static str)> {
+    ERROR_CODES
+        .iter()
+        .position(|c| c.eq_ignore_ascii_case(code))
+        .map(|i| (ERROR_MESSAGES[i], ERROR_EXPLANATIONS[i]))
 }
 
 //pub const PANIC_NON_KEY: &str =
@@ -165,13 +408,21 @@ fn const_concat() {
     let test = [0u8; 53];
     let test = map!(test: [u8; 53] |> i in 0..37 => { test[i] = i as u8 + 1 });
     let mut target = [0u8; 53];
-    for i in 0..37 {
-        target[i] = i as u8 + 1;
+    for (i, byte) in target.iter_mut().enumerate().take(37) {
+        *byte = i as u8 + 1;
     }
     assert_eq!(target, test);
 
     // Concat
-    const FIRST: &'static str = "The quick brown fox jumps over";
+    const FIRST: &str = "The quick brown fox jumps over";
     const_concat!(const ASDF = FIRST => " the lazy dog");
     assert_eq!("The quick brown fox jumps over the lazy dog", ASDF);
 }
+
+#[test]
+fn error_codes_are_unique() {
+    for (i, code) in ERROR_CODES.iter().enumerate() {
+        assert_eq!(*code, format!("CS{:04}", i + 1), "Codes must stay in order");
+    }
+    assert!(explain("cs0016").is_some());
+}
@@ -9,16 +9,52 @@ pub mod parser;
 mod reporter;
 mod templates;
 
-pub use templates::Consumer;
+pub use errors::{explain, ErrorKind, ALLOWABLE};
+pub use reporter::{JsonReport, MarkupError, OutputType, SourceMap};
+pub use templates::{Consumer, Fallback};
 
 use parser::shortcuts::ShortcutOwner;
 use templates::F;
 
-// @TODO: use runner
 #[derive(Debug)]
 pub struct Format<'a> {
     pub id: usize,
     pub runner: &'a str,
+    pub fallback: Fallback<'a>, // Only for the shell runner
+}
+
+// An entry of `chordscript-cli frameworks`
+#[derive(Debug)]
+pub struct Framework {
+    pub name: &'static str,
+    pub is_native: bool,
+    pub description: &'static str,
+    features: u8,
+}
+
+impl Framework {
+    pub fn features(&self) -> impl Iterator<Item = &'static str> + '_ {
+        templates::FEATURE_NAMES
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.features & (1 << i) != 0)
+            .map(|(_, name)| *name)
+    }
+}
+
+pub fn frameworks() -> impl Iterator<Item = Framework> {
+    templates::ID_TO_TYPE.iter().enumerate().map(|(i, format_type)| {
+        let (name, is_native) = match format_type {
+            F::N(name) => (*name, true),
+            F::S(name) => (*name, false),
+        };
+        Framework {
+            name,
+            is_native,
+            description: templates::DESCRIPTIONS[i],
+            features: templates::FEATURES[i],
+        }
+    })
 }
 
 #[derive(Debug)]
@@ -26,39 +62,59 @@ pub enum FormatError {
     Invalid,
     NativeUnsupported,
     ShellUnsupported,
+    // The format cannot represent one of the shortcuts
+    Unsupported(MarkupError),
 }
 
 impl<'a> Format<'a> {
     // Used by cli
     pub fn from_str(format: &str, maybe_runner: Option<&'a str>) -> Result<Self, FormatError> {
-        let id = templates::ID_TO_TYPE
-            .into_iter()
-            .enumerate()
+        // A framework can have both a native and a shell runner template
+        let mut iter = templates::ID_TO_TYPE.iter().enumerate();
+        let id = iter
+            .clone()
             .find_map(|(i, format_type)| match (format_type, maybe_runner) {
-                (F::N(name), None) if name == format => Some(Ok(i)),
-                (F::S(name), Some(_)) if name == format => Some(Ok(i)),
-                (F::S(name), Some(_)) if name == format => Some(Ok(i)),
-                (F::N(name), _) if name == format => Some(Err(FormatError::NativeUnsupported)),
-                (F::S(name), _) if name == format => Some(Err(FormatError::ShellUnsupported)),
+                (F::N(name), None) if *name == format => Some(i),
+                (F::S(name), Some(_)) if *name == format => Some(i),
                 _ => None,
             })
-            .unwrap_or(Err(FormatError::Invalid))?;
+            .ok_or_else(|| {
+                iter.find_map(|(_, format_type)| match format_type {
+                    F::N(name) if *name == format => Some(FormatError::ShellUnsupported),
+                    F::S(name) if *name == format => Some(FormatError::NativeUnsupported),
+                    _ => None,
+                })
+                .unwrap_or(FormatError::Invalid)
+            })?;
 
         Ok(Format {
             id,
-            runner: maybe_runner.unwrap_or("")
+            runner: maybe_runner.unwrap_or(""),
+            fallback: Fallback::Stderr,
         })
     }
 
-    pub fn pipe_stdout(&self, shortcut_owner: &ShortcutOwner, output: &mut std::io::Stdout) {
-        templates::VTABLE_STDOUT[self.id].pipe(shortcut_owner, output)
+    pub fn verify(&self, shortcut_owner: &ShortcutOwner) -> Result<(), FormatError> {
+        templates::VTABLE_VERIFY[self.id](shortcut_owner).map_err(FormatError::Unsupported)
+    }
+
+    pub fn pipe_stdout(
+        &self,
+        shortcut_owner: &ShortcutOwner,
+        output: &mut std::io::Stdout,
+    ) -> Result<(), FormatError> {
+        self.verify(shortcut_owner)?;
+        templates::VTABLE_STDOUT[self.id].pipe((shortcut_owner, self), output);
+        Ok(())
     }
 
-    pub fn pipe_to_string(&self, shortcut_owner: &ShortcutOwner) -> String {
+    pub fn pipe_to_string(&self, shortcut_owner: &ShortcutOwner) -> Result<String, FormatError> {
+        self.verify(shortcut_owner)?;
         let template = templates::VTABLE_STRING[self.id];
-        let mut buffer = String::with_capacity(template.len(shortcut_owner));
-        template.pipe(shortcut_owner, &mut buffer);
-        buffer
+        let input = (shortcut_owner, self);
+        let mut buffer = String::with_capacity(template.len(input));
+        template.pipe(input, &mut buffer);
+        Ok(buffer)
     }
     //pub fn deserialise<O: Consumer>(&self, shortcut_owner: &parser::shortcuts::ShortcutOwner, output: &mut O) {
     //    use templates::PreallocPush;
@@ -79,7 +135,7 @@ fn on_file() {
     use parser::{keyspaces, lexemes, shortcuts};
 
     //let path = concat!(env!("XDG_CONFIG_HOME"), "/rc/wm-shortcuts");
-    let path = concat!("./wm-shortcuts");
+    let path = "./wm-shortcuts";
     let file = std::fs::read_to_string(path).unwrap();
     let _lexemes = log(lexemes::lex(&file));
     //_lexemes.lexemes.iter().for_each(|l| println!("{:?}", l));
@@ -103,6 +159,16 @@ fn on_file() {
     //println!("{}", deserialise::KeyspacePreview(&_keyspaces).to_string_custom());
 }
 
+#[test]
+fn frameworks_are_formats() {
+    for framework in frameworks() {
+        let runner = if framework.is_native { None } else { Some("runner") };
+        assert!(Format::from_str(framework.name, runner).is_ok(), "{:?}", framework);
+    }
+    let debug = frameworks().find(|f| f.name == "debug-shortcuts").unwrap();
+    assert_eq!(debug.features().collect::<Vec<_>>(), ["multi-chord", "placeholders"]);
+}
+
 #[cfg(test)]
 fn log<T, E: std::fmt::Display>(wrapped: Result<T, E>) -> T {
     match wrapped {
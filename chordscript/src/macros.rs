@@ -10,7 +10,7 @@
 #[macro_export]
 macro_rules! sidebyside_len_and_push {
     (
-        $(! $( $prefix:ident )+ !)? $len:ident, $push_into:ident $(<$U2:ident>)?
+        $vis:vis $(! $( $prefix:ident )+ !)? $len:ident, $push_into:ident $(<$U2:ident>)?
             ($self:ident : $ty1:ty, $extra:ident : $ty2:ty, $buffer:ident: $U:ident)
         {
             $( $init:stmt; )*
@@ -18,11 +18,11 @@ macro_rules! sidebyside_len_and_push {
             $( $stmts:tt )*
         }
     ) => {
-        $( $( $prefix )* )? fn $len($self: $ty1, $extra: $ty2) -> usize {
+        $vis $( $( $prefix )* )? fn $len($self: $ty1, $extra: $ty2) -> usize {
             $( $init )*
             sidebyside_len_and_push!(@size $($stmts)*)
         }
-        fn $push_into $(<$U2: $crate::templates::Consumer>)? ($self: $ty1, $extra: $ty2, $buffer: &mut $U) {
+        $vis fn $push_into $(<$U2: $crate::templates::Consumer>)? ($self: $ty1, $extra: $ty2, $buffer: &mut $U) {
             //#[cfg(debug_assertions)]
             //{
             //    $( $init )*
@@ -68,7 +68,9 @@ macro_rules! pick {
     (1 => $me:expr $(=> $__:expr)*                          ) => { $me };
     (2 => $_1:expr => $me:expr $( => $__:expr)*             ) => { $me };
     (3 => $_1:expr => $_2:expr => $me:expr $( => $__:expr )*) => { $me };
-    (4 => $_1:expr => $_2:expr => $_3:expr => $me:expr      ) => { $me };
+    (4 => $_1:expr => $_2:expr => $_3:expr => $me:expr $( => $__:expr )*) => { $me };
+    (5 => $_1:expr => $_2:expr => $_3:expr => $_4:expr => $me:expr $( => $__:expr )*) => { $me };
+    (6 => $_1:expr => $_2:expr => $_3:expr => $_4:expr => $_5:expr => $me:expr        ) => { $me };
 }
 
 #[macro_export]
@@ -77,7 +79,7 @@ macro_rules! array_index_by_enum {
     pub enum $Enum:ident {
         $( $Variant:ident $( => $val:expr )* , )*
     } $( $rest:tt )*) => {
-        #[derive(Debug)]
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
         #[repr(usize)]
         pub enum $Enum {
             $( $Variant, )*
@@ -2,8 +2,11 @@ use std::fmt;
 use std::ops::Range;
 
 use crate::constants::{KEYCODES, MODIFIERS};
+use crate::errors::ALLOWABLE;
 use crate::reporter::MarkupError;
 
+pub mod conditions;
+pub mod includes;
 pub mod keyspaces;
 pub mod shortcuts;
 pub mod lexemes;
@@ -12,17 +15,43 @@ pub mod lexemes;
 
 ////////////////////////////////////////////////////////////////////////////////
 
-pub fn parse_to_shortcuts(input: &str) -> Result<shortcuts::ShortcutOwner, MarkupError>  {
+pub fn parse_to_shortcuts(input: &str) -> Result<shortcuts::ShortcutOwner<'_>, MarkupError> {
     let lexemes = lexemes::lex(input)?;
     shortcuts::parse_unsorted(lexemes)
 }
 
+// Every error in the file (in order) rather than stopping at the first
+pub fn parse_to_shortcuts_recover(input: &str) -> Result<shortcuts::ShortcutOwner<'_>, Vec<MarkupError>> {
+    parse_to_shortcuts_allowing(input, |_| false)
+}
+
+// Drops the errors in 'ALLOWABLE' that 'is_allowed', e.g. from `--allow`.
+// These are only reported without other errors, so the shortcuts are intact.
+pub fn parse_to_shortcuts_allowing<F>(input: &str, is_allowed: F) -> Result<shortcuts::ShortcutOwner<'_>, Vec<MarkupError>>
+where
+    F: Fn(&MarkupError) -> bool,
+{
+    let (lexemes, mut errors) = lexemes::lex_recover(input);
+    errors.retain(|err| !(ALLOWABLE.contains(&err.kind()) && is_allowed(err)));
+    match shortcuts::parse_unsorted_recover(lexemes) {
+        Ok(owner) if errors.is_empty() => Ok(owner),
+        Ok(_) => Err(errors),
+        Err(parse_errors) => {
+            errors.extend(parse_errors);
+            errors.sort_by_key(MarkupError::start);
+            Err(errors)
+        }
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 
 
 #[derive(Clone)]
 pub struct WithSpan<'filestr, T> {
     pub data: T,
+    // Every file spliced together with '@include', the 'SourceMap' in
+    // 'includes::Sources' has which file each line is from
     pub context: &'filestr str,
     pub source: &'filestr str,
 }
@@ -69,9 +98,16 @@ impl InnerChord {
     }
 }
 
+impl Default for InnerChord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Clone)]
 pub struct Chord<'filestr> {
     pub chord: InnerChord,
+    // The key at 0, then the modifiers by their 'Modifiers' id + 1
     pub sources: [&'filestr str; MODIFIERS.len() + 1],
     pub context: &'filestr str,
 }
@@ -95,6 +131,50 @@ impl<'filestr> Chord<'filestr> {
     }
 }
 
+// The smallest substring of 'context' that covers all of 'parts'
+// Empty parts (e.g. unused 'Chord::sources') and parts not from 'context'
+// (e.g. the escaped newline in the lexer) are ignored. Parts from '@let'
+// definitions are only covered if there is nothing else, otherwise the span
+// would stretch from the definition to where it is used.
+pub fn span_covering<'filestr, I>(context: &'filestr str, parts: I) -> &'filestr str
+where
+    I: Iterator<Item = &'filestr str>,
+{
+    let base = context.as_ptr() as usize;
+    let ranges = parts
+        .filter(|part| !part.is_empty())
+        .filter(|part| {
+            let index = part.as_ptr() as usize;
+            base <= index && index + part.len() <= base + context.len()
+        })
+        .map(|part| {
+            let index = part.as_ptr() as usize - base;
+            (index, index + part.len())
+        })
+        .collect::<Vec<_>>();
+    let is_definition = |(index, _): &(usize, usize)| {
+        let line_start = context[0..*index].rfind('\n').map_or(0, |i| i + 1);
+        lexemes::is_definition(&context[line_start..])
+    };
+    let is_all_definitions = ranges.iter().all(is_definition);
+    let (start, close) = ranges
+        .iter()
+        .filter(|range| is_all_definitions || !is_definition(range))
+        .fold((context.len(), 0), |(start, close), (a, b)| {
+            (start.min(*a), close.max(*b))
+        });
+    if start < close {
+        &context[start..close]
+    } else {
+        &context[0..0]
+    }
+}
+
+// For pointing at hotkeys in error messages
+pub fn hotkey_span<'filestr>(context: &'filestr str, hotkey: Hotkey<'_, 'filestr>) -> &'filestr str {
+    span_covering(context, hotkey.iter().flat_map(|chord| chord.sources.iter().copied()))
+}
+
 //#[cfg(debug_assertions)]
 //impl<'filestr> std::fmt::Display for Chord<'filestr> {
 //    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -140,7 +220,19 @@ pub type Hotkey<'owner, 'filestr> = &'owner [Chord<'filestr>];
 pub struct Shortcut<'owner, 'filestr> {
     pub is_placeholder: bool,
     pub hotkey: Hotkey<'owner, 'filestr>,
-    pub command: &'owner [WithSpan<'filestr, ()>],
+    // 'data' is true for a fragment from a '{{..}}' choice
+    pub command: &'owner [WithSpan<'filestr, bool>],
+    pub trim: Trim,
+}
+
+// What to do with the whitespace of a command, set by a marker just after the
+// closing '|'/'!', e.g. '|super a|<'. The templates do the trimming.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Trim {
+    #[default]
+    Keep,
+    Strip,  // '-', the whitespace at the start and end of the command
+    Dedent, // '<', also the indent that every line after the first shares
 }
 
 
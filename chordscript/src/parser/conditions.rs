@@ -0,0 +1,88 @@
+// '@if key=value' to '@end' only keeps the lines in between if the 'Profile'
+// matches. This is done by 'Sources' before lexing, so the lines can be whole
+// entries or just some of the lines of a command.
+
+use crate::constants::WHITESPACE;
+use crate::errors::ErrorKind;
+
+const IF: &str = "@if";
+const END: &str = "@end";
+
+// What '@if' can check, 'None' never matches
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Profile<'a> {
+    pub host: Option<&'a str>,
+    pub format: Option<&'a str>,
+}
+
+pub(super) fn is_if(line: &str) -> bool {
+    line.strip_prefix(IF)
+        .and_then(|rest| rest.chars().next())
+        .is_some_and(|c| WHITESPACE.contains(&c))
+}
+
+pub(super) fn is_end(line: &str) -> bool {
+    line.trim_end_matches(WHITESPACE) == END
+}
+
+impl Profile<'_> {
+    // 'line' is the whole '@if' line
+    pub(super) fn evaluate(&self, line: &str) -> Result<bool, ErrorKind> {
+        debug_assert!(is_if(line));
+        let (key, value) = line[IF.len()..]
+            .split_once('=')
+            .ok_or(ErrorKind::InvalidCondition)?;
+        let value = Some(value.trim_matches(WHITESPACE));
+        match key.trim_matches(WHITESPACE) {
+            "host" => Ok(self.host == value),
+            "format" => Ok(self.format == value),
+            _ => Err(ErrorKind::InvalidCondition),
+        }
+    }
+}
+
+#[test]
+fn conditions() {
+    let profile = Profile { host: Some("laptop"), format: None };
+    assert_eq!(profile.evaluate("@if host=laptop\n"), Ok(true));
+    assert_eq!(profile.evaluate("@if host = desktop"), Ok(false));
+    assert_eq!(profile.evaluate("@if format=sway"), Ok(false));
+    assert_eq!(profile.evaluate("@if laptop"), Err(ErrorKind::InvalidCondition));
+    assert_eq!(profile.evaluate("@if user=me"), Err(ErrorKind::InvalidCondition));
+    assert!(is_end("@end\r\n") && !is_end("@endif\n") && !is_if("@iffy"));
+}
+
+#[test]
+fn only_matching_lines_are_kept() {
+    use super::includes::Sources;
+
+    let shortcuts = "|super a| echo a\n\
+        @if host=laptop\n\
+        |XF86MonBrightnessUp| light -A 5\n\
+        @end\n\
+        |super q|\n\
+        @if format=sway\n\
+        \x20 swaymsg kill\n\
+        @end\n\
+        @if format=i3\n\
+        \x20 i3-msg kill\n\
+        @end\n\
+        |super a| echo again\n";
+    let read = |_: &str| unreachable!();
+    let profile = Profile { host: Some("desktop"), format: Some("i3") };
+    let sources = Sources::load("wm-shortcuts", shortcuts, &profile, read).unwrap();
+    assert_eq!(sources.text, "|super a| echo a\n|super q|\n  i3-msg kill\n|super a| echo again\n");
+    let errors = sources.parse_recover(&[]).unwrap_err();
+    assert!(errors[0].to_string().contains("\n12 | |super a| echo again\n"), "{}", errors[0]);
+
+    // A variable only used on another host is not unused
+    let shortcuts = "@let b = light\n@if host=laptop\n|XF86MonBrightnessUp| @{b} up\n@end\n|super a| echo\n";
+    let sources = Sources::load("wm-shortcuts", shortcuts, &profile, read).unwrap();
+    assert!(sources.parse_recover(&[]).is_ok());
+    let sources = Sources::load("wm-shortcuts", "@let b = light\n|super a| echo\n", &profile, read).unwrap();
+    assert_eq!(sources.parse_recover(&[]).unwrap_err()[0].kind(), ErrorKind::UnusedVariable);
+
+    let kind = |text| Sources::load("wm-shortcuts", text, &profile, read).unwrap_err().kind();
+    assert_eq!(kind("@if host=laptop\n|a| b\n"), ErrorKind::UnclosedCondition);
+    assert_eq!(kind("|a| b\n@end\n"), ErrorKind::UnmatchedEnd);
+}
@@ -0,0 +1,240 @@
+// '@include path' splices the file in just after the '@include' line, so all
+// the lexemes still point into one string. The 'SourceMap' remembers which
+// file each line came from for error messages.
+//
+// The lexer skips the '@include' lines themselves, it is this module that
+// reads the files. Paths are relative to the file with the '@include'.
+// The '@if' conditions are also done here, see 'conditions.rs'.
+
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::constants::WHITESPACE;
+use crate::errors::{includes as errors, ErrorKind};
+use crate::reporter::{MarkupError, SourceMap};
+
+use super::conditions::{is_end, is_if, Profile};
+use super::shortcuts::ShortcutOwner;
+
+const INCLUDE: &str = "@include";
+// Symbolic links can make a cycle with paths that never repeat
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug)]
+pub struct Sources {
+    pub text: String,
+    pub source_map: SourceMap,
+    // The lines that an '@if' removed, a variable used only there is not unused
+    pub inactive: String,
+}
+
+// If 'line' starts with an include
+pub(super) fn is_include(line: &str) -> bool {
+    line.strip_prefix(INCLUDE)
+        .map(|rest| rest.chars().next().is_none_or(|c| WHITESPACE.contains(&c)))
+        .unwrap_or(false)
+}
+
+impl Sources {
+    // 'root' is the contents of the file at 'path'. 'read' is for every file
+    // that is included.
+    pub fn load<F>(path: &str, root: &str, profile: &Profile, mut read: F) -> Result<Self, MarkupError>
+    where
+        F: FnMut(&str) -> io::Result<String>,
+    {
+        let mut sources = Self {
+            text: String::with_capacity(root.len()),
+            source_map: SourceMap::default(),
+            inactive: String::new(),
+        };
+        let mut splice = Splice { profile, stack: Vec::new(), read: &mut read };
+        splice.file(&mut sources, path, root)?;
+        Ok(sources)
+    }
+
+    // Errors will point to the right file. The kinds in 'allow' are dropped,
+    // see 'parse_to_shortcuts_allowing()'.
+    pub fn parse_recover(&self, allow: &[ErrorKind]) -> Result<ShortcutOwner<'_>, Vec<MarkupError>> {
+        let is_allowed = |err: &MarkupError| {
+            allow.contains(&err.kind())
+                || err.kind() == ErrorKind::UnusedVariable && self.inactive.contains(&format!("@{{{}}}", err.span()))
+        };
+        super::parse_to_shortcuts_allowing(&self.text, is_allowed).map_err(|errors| {
+            errors
+                .into_iter()
+                .map(|err| err.with_source_map(&self.source_map))
+                .collect()
+        })
+    }
+}
+
+// The state shared by every file that is being spliced together
+struct Splice<'a, F> {
+    profile: &'a Profile<'a>,
+    stack: Vec<String>, // The files currently being included, for cycles
+    read: &'a mut F,
+}
+
+impl<F: FnMut(&str) -> io::Result<String>> Splice<'_, F> {
+    fn file(&mut self, sources: &mut Sources, path: &str, text: &str) -> Result<(), MarkupError> {
+        let file = sources.source_map.add_file(path);
+        self.stack.push(path.to_string());
+
+        // One entry for every '@if' that is open, if it matched
+        let mut conditions: Vec<(bool, &str)> = Vec::new();
+        let mut is_skipping = true; // i.e. needs a new segment
+        let mut is_literal = false; // Inside '{{{..}}}', lines are never directives
+        let mut byte = 0;
+        for (i, line) in text.split_inclusive('\n').enumerate() {
+            let line_start = byte;
+            byte += line.len();
+            let trimmed = line.trim_end_matches(WHITESPACE);
+            let error = |kind| {
+                MarkupError::from_str(text, trimmed, kind).with_source_map(&SourceMap::single(path))
+            };
+
+            if !is_literal && is_if(line) {
+                conditions.push((self.profile.evaluate(line).map_err(error)?, trimmed));
+                is_skipping = true;
+                continue;
+            } else if !is_literal && is_end(line) {
+                conditions.pop().ok_or_else(|| error(ErrorKind::UnmatchedEnd))?;
+                is_skipping = true;
+                continue;
+            } else if !conditions.iter().all(|(is_match, _)| *is_match) {
+                sources.inactive.push_str(line);
+                is_skipping = true;
+                continue;
+            }
+
+            if is_skipping {
+                sources.source_map.add_segment(sources.text.len(), file, i + 1, line_start);
+                is_skipping = false;
+            }
+            sources.text.push_str(line);
+            let was_literal = is_literal;
+            is_literal = is_literal_after(line, was_literal);
+            if was_literal || !is_include(line) {
+                continue;
+            }
+
+            let target = trimmed[INCLUDE.len()..].trim_start_matches(WHITESPACE);
+            let resolved = resolve(path, target);
+            if target.is_empty() {
+                return Err(error(ErrorKind::InvalidInclude));
+            } else if self.stack.contains(&resolved) {
+                let chain = self.stack.iter().map(String::as_str).chain([resolved.as_str()]);
+                return Err(error(ErrorKind::IncludeCycle).with_note(&chain.collect::<Vec<_>>().join(" -> ")));
+            } else if self.stack.len() >= MAX_DEPTH {
+                return Err(error(ErrorKind::IncludeCycle).with_note(errors::TOO_DEEP));
+            }
+            let included = (self.read)(&resolved)
+                .map_err(|err| error(ErrorKind::IncludeUnreadable).with_note(&err.to_string()))?;
+
+            if !sources.text.ends_with('\n') {
+                sources.text.push('\n');
+            }
+            self.file(sources, &resolved, &included)?;
+            if !sources.text.ends_with('\n') {
+                sources.text.push('\n');
+            }
+            is_skipping = true;
+        }
+
+        // An '@if' cannot continue into the file that included this one
+        if let Some((_, line)) = conditions.last() {
+            let err = MarkupError::from_str(text, line, ErrorKind::UnclosedCondition);
+            return Err(err.with_source_map(&SourceMap::single(path)));
+        }
+        self.stack.pop();
+        Ok(())
+    }
+}
+
+// If a '{{{' literal group is still open after 'line', the lexer does the
+// same in commands. Comments can have a '{{{' as a fold marker.
+fn is_literal_after(line: &str, mut is_literal: bool) -> bool {
+    let mut rest = line;
+    if !is_literal && rest.starts_with('#') {
+        return false;
+    }
+    while let Some(i) = rest.find(if is_literal { "}}}" } else { "{{{" }) {
+        rest = &rest[i + "{{{".len()..];
+        is_literal = !is_literal;
+    }
+    is_literal
+}
+
+// Relative to the directory of 'including', without any '.' or '..' so that
+// the same file always has the same path
+fn resolve(including: &str, target: &str) -> String {
+    let base = Path::new(including).parent().unwrap_or(Path::new(""));
+    let mut resolved = PathBuf::new();
+    for component in base.join(target).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(resolved.components().next_back(), Some(Component::Normal(_))) => {
+                resolved.pop();
+            }
+            _ => resolved.push(component),
+        }
+    }
+    resolved.to_string_lossy().into_owned()
+}
+
+#[test]
+fn includes_are_spliced() {
+    let read = |path: &str| match path {
+        "config/shared" => Ok("|super a| echo a\n|super a| echo again".to_string()),
+        "config/laptop" => Ok("@include ./shared\n".to_string()),
+        "config/loop" => Ok("@include ../config/loop\n".to_string()),
+        _ => Err(io::Error::new(io::ErrorKind::NotFound, "No such file")),
+    };
+    let profile = Profile::default();
+
+    let sources = Sources::load("config/main", "# main\n@include laptop\n|super b| echo b\n", &profile, read).unwrap();
+    assert_eq!(
+        sources.text,
+        "# main\n@include laptop\n@include ./shared\n|super a| echo a\n|super a| echo again\n|super b| echo b\n"
+    );
+    let errors = sources.parse_recover(&[]).unwrap_err();
+    assert_eq!(
+        errors[0].to_string(),
+        "error[CS0019]\n --> config/shared:2:2\n  |\n\
+        1 | |super a| echo a\n  |  ------- first defined here\n\
+        2 | |super a| echo again\n  |  ^^^^^^^\n  = This hotkey is defined previously.\n"
+    );
+
+    // Labels in another file are under its name
+    let sources = Sources::load("config/main", "@include shared\n|super a| echo b\n", &profile, read).unwrap();
+    let errors = sources.parse_recover(&[]).unwrap_err();
+    assert!(
+        errors[1].to_string().contains(
+            "  |\n ::: config/shared\n1 | |super a| echo a\n  |  ------- first defined here\n\
+            \x20::: config/main\n2 | |super a| echo b\n  |  ^^^^^^^\n"
+        ),
+        "{}",
+        errors[1]
+    );
+
+    let err = Sources::load("config/main", "@include loop\n", &profile, read).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::IncludeCycle);
+    assert!(err.to_string().contains("config/loop -> config/loop\n"), "{}", err);
+    let err = Sources::load("config/main", "\n@include missing\n", &profile, read).unwrap_err();
+    assert!(err.to_string().starts_with("error[CS0037]\n --> config/main:2:1\n"), "{}", err);
+}
+
+#[test]
+fn literals_are_not_directives() {
+    let read = |_: &str| unreachable!();
+    let profile = Profile::default();
+
+    let shortcuts = "# Heredocs {{{\n\
+        |super a| cat <<EOF {{{\n@end\n@include other\n}}}\nEOF\n\
+        @if host=laptop\n|super b| echo\n@end\n";
+    let sources = Sources::load("wm-shortcuts", shortcuts, &profile, read).unwrap();
+    assert_eq!(sources.text, "# Heredocs {{{\n|super a| cat <<EOF {{{\n@end\n@include other\n}}}\nEOF\n");
+    let owner = sources.parse_recover(&[]).unwrap();
+    let shell = crate::Format::from_str("shell", None).unwrap().pipe_to_string(&owner).unwrap();
+    assert!(shell.contains(";; 'super a')   cat <<EOF \n@end\n@include other\n\nEOF\n"), "{}", shell);
+}
@@ -2,11 +2,12 @@
 
 use std::mem::swap;
 
-use crate::constants::SEPARATOR;
-use crate::errors::lexer as errors;
+use crate::constants::{SEPARATOR, WHITESPACE};
+use crate::errors::{lexer as errors, ErrorKind};
 use crate::reporter::MarkupError;
 
-use super::Cursor;
+use super::includes::is_include;
+use super::{Cursor, Trim};
 
 // For escaping during the lexer phase, State::BEscape
 // We introduce a new value with "\n" not in the original text.
@@ -38,6 +39,7 @@ pub struct LexOutput<'filestr> {
 #[derive(Debug)]
 pub struct PostLexEntry {
     pub is_placeholder: bool,
+    pub trim: Trim,
     pub head_size: usize,
     pub body_size: usize,
     pub permutations: usize,
@@ -46,7 +48,22 @@ pub struct PostLexEntry {
     pub tail: usize,
 }
 
-pub fn lex(input: &str) -> Result<LexOutput, MarkupError> {
+pub fn lex(input: &str) -> Result<LexOutput<'_>, MarkupError> {
+    let (output, mut errors) = lex_main(input, false);
+    match errors.pop() {
+        Some(err) => Err(err),
+        None => Ok(output),
+    }
+}
+
+// Instead of stopping at the first error, skip to the next entry (a line
+// starting with '|' or '!') and keep lexing. Only entries without errors
+// make it into the 'LexOutput'.
+pub fn lex_recover(input: &str) -> (LexOutput<'_>, Vec<MarkupError>) {
+    lex_main(input, true)
+}
+
+fn lex_main(input: &str, recover: bool) -> (LexOutput<'_>, Vec<MarkupError>) {
     let entry_estimate = input
         .lines()
         .map(|line| line.chars().next().unwrap_or(' '))
@@ -59,6 +76,7 @@ pub fn lex(input: &str) -> Result<LexOutput, MarkupError> {
         cursor: Cursor(0),
         original: input,
         is_placeholder: false,
+        trim: Trim::Keep,
         state: State::Head,
         old_state: State::Head,
 
@@ -67,14 +85,27 @@ pub fn lex(input: &str) -> Result<LexOutput, MarkupError> {
         entry_body_index: 0,
         member_num: 0,        // index for HChoice/BChoice (to `filter()` on in parser)
         max_permutes: (1, 1), // (head max, body max)
+        bracket_open: 0,
+        literal_open: 0,
 
         chord_count: (0, 0), // (outside, inside) permute group '{{' '}}'
         body_count: (0, 0),
+
+        awaiting_entry: true,
+        variables: Vec::new(),
+        queued: Vec::new(),
     };
 
     // Start lexing
-    step_init(&mut fsm)?;
-    while let Some(ch) = fsm.walker.next() {
+    let mut errors = Vec::new();
+    let mut is_eof = match step_init(&mut fsm) {
+        Ok(_) => false,
+        Err(err) => {
+            errors.push(err);
+            !recover || !fsm.resync(fragments.len(), &mut errors)
+        }
+    };
+    while let (false, Some(ch)) = (is_eof, fsm.walker.next()) {
         let len = fragments.len();
         let maybe_push = match fsm.state {
             State::Head => step_head_placeholder(&mut fsm, ch, len),
@@ -82,14 +113,25 @@ pub fn lex(input: &str) -> Result<LexOutput, MarkupError> {
             State::Body => step_body(&mut fsm, ch, len),
             State::BBrackets => step_b_brackets(&mut fsm, ch),
             State::BEscape => step_b_escape(&mut fsm, ch),
-        }?;
-        if let Some(item) = maybe_push {
-            fragments.push(item);
+            State::BLiteral => step_b_literal(&mut fsm, ch),
+        };
+        // Lexemes from expanding a variable come before the returned one
+        fragments.append(&mut fsm.queued);
+        match maybe_push {
+            Ok(Some(item)) => fragments.push(item),
+            Ok(None) => {}
+            Err(err) => {
+                errors.push(err);
+                // Throw away the lexemes of the entry with the error
+                fragments.truncate(fsm.entry_head_index);
+                is_eof = !recover || !fsm.resync(fragments.len(), &mut errors);
+            }
         }
     }
 
     // The while loop ends before last lexeme in the body is pushed
-    match fsm.state {
+    let last_entry = match fsm.state {
+        _ if is_eof => Ok(None),
         State::Body => {
             if let Ok(Some(lexeme)) = fsm.emit_body(&input[fsm.cursor.0..]) {
                 fragments.push(lexeme)
@@ -98,22 +140,42 @@ pub fn lex(input: &str) -> Result<LexOutput, MarkupError> {
             fsm.push_entry(false, fragments.len());
             Ok(None)
         }
+        // Only definitions after the last entry
+        State::Head if fsm.awaiting_entry && (!fsm.entry_stats.is_empty() || !errors.is_empty()) => Ok(None),
+        // Only comments and definitions, if anything
+        State::Head if fsm.awaiting_entry => fsm.walker.error_at_current(ErrorKind::NoEntries),
         State::Head if fsm.is_placeholder => fsm
             .walker
-            .error_at_current(errors::END_BEFORE_PLACEHOLDER_CLOSE),
-        State::Head => fsm.walker.error_at_current(errors::END_BEFORE_HEAD_CLOSE),
+            .error_at_current(ErrorKind::EndBeforePlaceholderClose),
+        State::Head => fsm.walker.error_at_current(ErrorKind::EndBeforeHeadClose),
         State::BEscape | State::HBrackets | State::BBrackets => fsm
-            .walker
-            .error_at_current(errors::END_BEFORE_BRACKET_CLOSE),
-    }?;
+            .error_in_brackets(ErrorKind::EndBeforeBracketClose),
+        State::BLiteral => fsm.error_in_literal(),
+    };
+    if let Err(err) = last_entry {
+        errors.push(err);
+        fragments.truncate(fsm.entry_head_index);
+    }
+
+    // Entries skipped after an error might have used them
+    if errors.is_empty() {
+        errors.extend(
+            fsm.variables
+                .iter()
+                .filter(|variable| !variable.is_used)
+                .map(|variable| MarkupError::from_str(input, variable.name, ErrorKind::UnusedVariable))
+                .take(if recover { usize::MAX } else { 1 }),
+        );
+    }
 
     //fragments.iter().for_each(|lexeme| println!("- {:?}", lexeme));
 
-    Ok(LexOutput {
+    let output = LexOutput {
         entry_stats: fsm.entry_stats,
         lexemes: fragments,
         original: input,
-    })
+    };
+    (output, errors)
 }
 
 #[derive(Debug)]
@@ -123,6 +185,7 @@ enum State {
     Body,
     BBrackets,
     BEscape,
+    BLiteral,
 }
 
 // Finite State Machine
@@ -134,6 +197,7 @@ struct Fsm<'a> {
 
     original: &'a str,
     is_placeholder: bool,
+    trim: Trim, // Of the current entry
     state: State,
     // This is more useful when we have more states
     old_state: State,
@@ -144,10 +208,24 @@ struct Fsm<'a> {
     entry_body_index: usize,
     member_num: usize,
     max_permutes: (usize, usize),
+    bracket_open: usize, // Index of the last '{{', for error messages
+    literal_open: usize, // Index of the last '{{{'
 
     // Counts (outside, inside) permute group '{{' '}}'
     chord_count: (usize, usize), // This follows ';' and '|'/'!'
     body_count: (usize, usize),  // This follows ',' and '}}'
+
+    awaiting_entry: bool, // Between entries, i.e. in `step_init()`
+    variables: Vec<Variable<'a>>,
+    queued: Vec<Lexeme<'a>>, // Pushed before the output of each step
+}
+
+// From '@let name = value', both point into the definition line
+#[derive(Debug)]
+struct Variable<'a> {
+    name: &'a str,
+    value: &'a str,
+    is_used: bool,
 }
 
 /******************************************************************************
@@ -155,18 +233,21 @@ struct Fsm<'a> {
  ******************************************************************************/
 #[inline]
 fn step_init<'a>(fsm: &mut Fsm<'a>) -> StepOutput<'a> {
+    fsm.awaiting_entry = true;
     while let Some(peek) = fsm.walker.peek() {
         match (fsm.walker.curr_char, peek) {
             ('\n', '#') => fsm.walker.eat_till_newline(),
+            ('\n', '@') => fsm.directive()?,
             ('\n', c @ '|' | c @ '!') => {
                 debug_assert!(matches!(fsm.state, State::Head));
                 fsm.walker.next(); // skip newline (and '|' after break)
                 fsm.is_placeholder = c == '!';
+                fsm.awaiting_entry = false;
                 break;
             }
 
             ('\n', _) => {}
-            _ => return fsm.walker.error_at_current(errors::INVALID_LINE_START),
+            _ => return fsm.walker.error_at_current(ErrorKind::InvalidLineStart),
         }
         fsm.walker.next();
     }
@@ -189,6 +270,7 @@ fn step_head_placeholder<'a>(fsm: &mut Fsm<'a>, ch: char, lexeme_count: usize) -
 
         ('{', Some('{')) => {
             fsm.change_state(State::Head, State::HBrackets);
+            fsm.bracket_open = fsm.walker.prev;
 
             let before_bracket = fsm.cursor.move_to(fsm.walker.prev);
             fsm.walker.next(); // Skip second '{'
@@ -196,6 +278,7 @@ fn step_head_placeholder<'a>(fsm: &mut Fsm<'a>, ch: char, lexeme_count: usize) -
             fsm.member_num = 0;
             fsm.emit_head(&fsm.original[before_bracket])
         }
+        ('@', Some('{')) => fsm.expand(),
 
         (';', _) => {
             // i.e. cursor is pointing at semicolon
@@ -232,10 +315,11 @@ fn step_head_placeholder<'a>(fsm: &mut Fsm<'a>, ch: char, lexeme_count: usize) -
                         1
                     },
             )?;
+            fsm.read_trim();
             lexeme
         }
 
-        ('!', _) if !fsm.is_placeholder => fsm.walker.error_at_current(errors::EXCLAIM_IN_HEAD),
+        ('!', _) if !fsm.is_placeholder => fsm.walker.error_at_current(ErrorKind::ExclaimInHead),
         ('!', _) => {
             fsm.change_state(State::Head, State::Body);
 
@@ -252,12 +336,13 @@ fn step_head_placeholder<'a>(fsm: &mut Fsm<'a>, ch: char, lexeme_count: usize) -
                         1
                     },
             )?;
+            fsm.read_trim();
             lexeme
         }
-        ('|', _) => fsm.walker.error_at_current(errors::BAR_IN_PLACEHOLDER),
+        ('|', _) => fsm.walker.error_at_current(ErrorKind::BarInPlaceholder),
 
         /**********************************/
-        ('{', _) => fsm.walker.error_at_current(errors::MISSING_LBRACKET),
+        ('{', _) => fsm.walker.error_at_current(ErrorKind::MissingLBracket),
 
         (_, Some('|' | '!' | ';')) => {
             let before_head_end = fsm.cursor.move_to(fsm.walker.post);
@@ -276,8 +361,9 @@ fn step_h_brackets<'a>(fsm: &mut Fsm<'a>, ch: char) -> StepOutput<'a> {
             fsm.cursor.move_to(fsm.walker.post);
             fsm.emit_h_choice(&fsm.original[before_newline])
         }
-        ('|', _) => fsm.walker.error_at_current(errors::HEAD_INVALID_CLOSE),
-        ('\\', _) => fsm.walker.error_at_current(errors::HEAD_NO_ESCAPING),
+        ('|', _) => fsm.walker.error_at_current(ErrorKind::HeadInvalidClose),
+        ('\\', _) => fsm.walker.error_at_current(ErrorKind::HeadNoEscaping),
+        ('@', Some('{')) => fsm.expand(),
         (',', _) => {
             let before_comma = fsm.cursor.move_to(fsm.walker.prev);
             fsm.walker.eat_separator();
@@ -314,7 +400,7 @@ fn step_h_brackets<'a>(fsm: &mut Fsm<'a>, ch: char) -> StepOutput<'a> {
             fsm.emit_h_choice(&fsm.original[before_blank])
         }
 
-        ('}', _) => fsm.walker.error_at_current(errors::MISSING_RBRACKET),
+        ('}', _) => fsm.walker.error_at_current(ErrorKind::MissingRBracket),
 
         (_, Some(';')) => {
             let before_chord_end = fsm.cursor.move_to(fsm.walker.post);
@@ -336,8 +422,14 @@ fn step_body<'a>(fsm: &mut Fsm<'a>, ch: char, lexeme_count: usize) -> StepOutput
             fsm.emit_body(&fsm.original[before_newline])
         }
 
+        ('{', Some('{')) if fsm.original[fsm.walker.post..].starts_with("{{") => {
+            fsm.change_state(State::Body, State::BLiteral);
+            let before_literal = fsm.open_literal();
+            fsm.emit_body(&fsm.original[before_literal])
+        }
         ('{', Some('{')) => {
             fsm.change_state(State::Body, State::BBrackets);
+            fsm.bracket_open = fsm.walker.prev;
 
             let before_brackets = fsm.cursor.move_to(fsm.walker.prev);
             fsm.walker.next(); // Skip second '{'
@@ -345,6 +437,25 @@ fn step_body<'a>(fsm: &mut Fsm<'a>, ch: char, lexeme_count: usize) -> StepOutput
             fsm.member_num = 0;
             fsm.emit_body(&fsm.original[before_brackets])
         }
+        ('@', Some('{')) => fsm.expand(),
+        // '\@{name}' is output as '@{name}', e.g. for git's 'git log @{u}'
+        ('\\', Some('@')) if reference_name(&fsm.original[fsm.walker.post + 1..]).is_some() => {
+            let before_backslash = fsm.cursor.move_to(fsm.walker.prev);
+            fsm.cursor.move_to(fsm.walker.post);
+            fsm.walker.next(); // Skip the '@' so it is not expanded
+            fsm.emit_body(&fsm.original[before_backslash])
+        }
+
+        // A directive also ends the body
+        ('\n', Some('@')) if is_directive(&fsm.original[fsm.walker.post..]) => {
+            fsm.change_state(State::Body, State::Head);
+
+            let before_newline = fsm.cursor.move_to(fsm.walker.prev);
+            let lexeme = fsm.emit_body(&fsm.original[before_newline]);
+            fsm.queue(lexeme)?;
+            fsm.push_entry(false, lexeme_count + fsm.queued.len());
+            step_init(fsm)
+        }
 
         ('\n', Some(c @ '|') | Some(c @ '!')) => {
             fsm.change_state(State::Body, State::Head);
@@ -384,8 +495,8 @@ fn step_b_brackets<'a>(fsm: &mut Fsm<'a>, ch: char) -> StepOutput<'a> {
         }
 
         ('|', _) if fsm.walker.last_char == '\n' => fsm
-            .walker
-            .error_at_current(errors::BODY_BRACKET_NO_NEWLINE_BAR),
+            .error_in_brackets(ErrorKind::BodyBracketNoNewlineBar),
+        ('@', Some('{')) => fsm.expand(),
         (',', _) => {
             let before_comma = fsm.cursor.span_to(fsm.walker.prev);
             // was 'emite_b_member()'
@@ -395,7 +506,7 @@ fn step_b_brackets<'a>(fsm: &mut Fsm<'a>, ch: char) -> StepOutput<'a> {
 
             if fsm.member_num > fsm.max_permutes.0 {
                 fsm.walker
-                    .error_at_current(errors::MORE_BODY_THAN_HEAD_PERMUTATIONS)
+                    .error_at_current(ErrorKind::MoreBodyThanHeadPermutations)
             } else {
                 frag
             }
@@ -410,15 +521,41 @@ fn step_b_brackets<'a>(fsm: &mut Fsm<'a>, ch: char) -> StepOutput<'a> {
 
             if fsm.max_permutes.1 > fsm.max_permutes.0 {
                 fsm.walker
-                    .error_at_current(errors::MORE_BODY_THAN_HEAD_PERMUTATIONS)
+                    .error_at_current(ErrorKind::MoreBodyThanHeadPermutations)
             } else {
                 // was 'emite_b_member()'
                 fsm.emit_b_choice(&fsm.original[before_bracket])
             }
         }
+        ('{', Some('{')) if fsm.original[fsm.walker.post..].starts_with("{{") => {
+            fsm.change_state(State::BBrackets, State::BLiteral);
+            let before_literal = fsm.open_literal();
+            fsm.emit_b_choice(&fsm.original[before_literal])
+        }
         ('{', Some('{')) => fsm
-            .walker
-            .error_at_current(errors::DOUBLE_LBRACKET_IN_BODY_PERMUTATION_GROUP),
+            .error_in_brackets(ErrorKind::DoubleLBracketInBodyPermutationGroup),
+        _ => Ok(None),
+    }
+}
+
+// Everything up to '}}}' is output as is, even '|' or '#' at the start of a
+// line, ',' in permutation groups, and '@{name}'
+#[inline]
+fn step_b_literal<'a>(fsm: &mut Fsm<'a>, ch: char) -> StepOutput<'a> {
+    match (ch, fsm.walker.peek()) {
+        ('}', Some('}')) if fsm.original[fsm.walker.post..].starts_with("}}") => {
+            // Back to 'State::Body' or 'State::BBrackets'
+            swap(&mut fsm.state, &mut fsm.old_state);
+
+            let before_literal = fsm.cursor.span_to(fsm.walker.prev);
+            fsm.walker.next(); // Skip the second and third '}'
+            fsm.walker.next();
+            fsm.cursor.move_to(fsm.walker.post);
+            match fsm.state {
+                State::Body => fsm.emit_body(&fsm.original[before_literal]),
+                _ => fsm.emit_b_choice(&fsm.original[before_literal]),
+            }
+        }
         _ => Ok(None),
     }
 }
@@ -461,7 +598,7 @@ macro_rules! match_and_build_escapes {
 
 match_and_build_escapes! {
     step_b_escape(fsm) {
-        '\\' | '|' | ',' => {
+        '\\' | '|' | ',' | '@' => {
             let after_escaped = fsm.cursor.move_to(fsm.walker.post);
             fsm.emit_b_choice(&fsm.original[after_escaped])
         },
@@ -478,7 +615,7 @@ match_and_build_escapes! {
             Ok(None)
         },
 
-        _ => fsm.walker.error_at_current(errors::INVALID_ESCAPE),
+        _ => fsm.walker.error_at_current(ErrorKind::InvalidEscape),
     }
 }
 
@@ -501,16 +638,220 @@ impl<'a> Fsm<'a> {
 
             (State::BBrackets, State::BEscape) => {}
             (State::BEscape, State::BBrackets) => {}
-            (a, b) => {
-                let m = format!("Invalid state transition {:?} -> {:?}", a, b);
-                let err = self.walker.error_at_current(m.as_str()).unwrap_err();
-                unreachable!("{:?}", err);
-            }
+            (State::Body | State::BBrackets, State::BLiteral) => {}
+            (a, b) => unreachable!(
+                "Invalid state transition {:?} -> {:?} at byte {}",
+                a, b, self.walker.prev
+            ),
         }
 
         self.old_state = from_state;
         self.state = into_state;
     }
+
+    // Skip past the rest of the entry with an error to the start of the next
+    // entry (or directive). Returns false if there are no more entries.
+    fn resync(&mut self, lexeme_count: usize, errors: &mut Vec<MarkupError>) -> bool {
+        loop {
+            match (self.walker.curr_char, self.walker.peek()) {
+                ('\n', Some('|' | '!')) => break,
+                ('\n', Some('@')) if is_directive(&self.original[self.walker.post..]) => break,
+                (_, None) => return false,
+                _ => {
+                    self.walker.next();
+                }
+            }
+        }
+        self.state = State::Head;
+        self.old_state = State::Head;
+        self.trim = Trim::Keep;
+        self.entry_head_index = lexeme_count;
+        self.member_num = 0;
+        self.max_permutes = (1, 1);
+        self.chord_count = (0, 0);
+        self.body_count = (0, 0);
+        match step_init(self) {
+            Ok(_) => !self.awaiting_entry,
+            Err(err) => {
+                errors.push(err);
+                self.resync(lexeme_count, errors)
+            }
+        }
+    }
+
+    // Returns the text before the '{{{' and skips to after it
+    fn open_literal(&mut self) -> std::ops::Range<usize> {
+        self.literal_open = self.walker.prev;
+        let before_literal = self.cursor.move_to(self.walker.prev);
+        self.walker.next(); // Skip the second and third '{'
+        self.walker.next();
+        self.cursor.move_to(self.walker.post);
+        before_literal
+    }
+
+    // Also points at the '{{' that is still open
+    fn error_in_brackets(&self, kind: ErrorKind) -> StepOutput<'a> {
+        let open = &self.original[self.bracket_open..self.bracket_open + "{{".len()];
+        self.walker.error_at_current(kind).map_err(|err| {
+            err.with_label(self.original, open, errors::BRACKET_OPENED.to_string())
+        })
+    }
+
+    fn error_in_literal(&self) -> StepOutput<'a> {
+        let open = &self.original[self.literal_open..self.literal_open + "{{{".len()];
+        self.walker.error_at_current(ErrorKind::EndBeforeLiteralClose).map_err(|err| {
+            err.with_label(self.original, open, errors::LITERAL_OPENED.to_string())
+        })
+    }
+}
+
+/******************************************************************************
+ * Directives, '@include path', and variables, '@let name = value' and '@{name}'
+ ******************************************************************************/
+const DEFINE: &str = "@let";
+
+// Bodies can still have lines that start with other '@'
+fn is_directive(line: &str) -> bool {
+    is_definition(line) || is_include(line)
+}
+
+// If 'line' starts with a definition
+pub(super) fn is_definition(line: &str) -> bool {
+    line.strip_prefix(DEFINE)
+        .and_then(|rest| rest.chars().next())
+        .is_some_and(|c| c != '\n' && WHITESPACE.contains(&c))
+}
+
+// 'rest' starts just after the '@'
+fn reference_name(rest: &str) -> Option<&str> {
+    let inside = rest.strip_prefix('{')?;
+    let len = inside.find(|c: char| !c.is_ascii_alphanumeric() && c != '_')?;
+    let name = &inside[0..len];
+    let is_valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+    (is_valid && inside[len..].starts_with('}')).then_some(name)
+}
+
+// A value in a head is a list of keys and ';'
+fn head_words(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(|c| SEPARATOR.contains(&c))
+        .flat_map(|word| word.split_inclusive(';'))
+        .flat_map(|word| match word.strip_suffix(';') {
+            Some(key) => [key, &word[key.len()..]],
+            None => [word, ""],
+        })
+        .filter(|word| !word.is_empty())
+}
+
+impl<'a> Fsm<'a> {
+    // I assume 'self.walker' is on the newline before the '@'
+    fn directive(&mut self) -> Output<()> {
+        let start = self.walker.post;
+        let close = self.original[start..].find('\n').map_or(self.original.len(), |i| start + i);
+        let line = &self.original[start..close];
+        self.walker.eat_till_newline();
+
+        // The file is already spliced in after this line by 'Sources'
+        if is_include(line) {
+            Ok(())
+        } else {
+            self.define(line)
+        }
+    }
+
+    fn define(&mut self, line: &'a str) -> Output<()> {
+        let invalid = || MarkupError::from_str(self.original, line, ErrorKind::InvalidDefinition);
+        if !line.starts_with(DEFINE) {
+            return Err(MarkupError::from_str(self.original, line, ErrorKind::InvalidLineStart));
+        } else if !is_definition(line) {
+            return Err(invalid());
+        }
+        let rest = line[DEFINE.len()..].trim_start_matches(WHITESPACE);
+        let len = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+        let name = &rest[0..len];
+        let value = rest[len..]
+            .trim_start_matches(WHITESPACE)
+            .strip_prefix('=')
+            .ok_or_else(invalid)?
+            .trim_matches(WHITESPACE);
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') || value.contains("@{") {
+            return Err(invalid());
+        }
+
+        if let Some(first) = self.variables.iter().find(|variable| variable.name == name) {
+            Err(MarkupError::from_str(self.original, name, ErrorKind::RedefinedVariable)
+                .with_label(self.original, first.name, errors::VARIABLE_FIRST_DEFINED.to_string()))
+        } else {
+            self.variables.push(Variable { name, value, is_used: false });
+            Ok(())
+        }
+    }
+
+    // Replaces '@{name}' with the value of the variable. The lexemes point
+    // into the definition, so errors in the value are reported there.
+    // Anything that is not a valid name is left as is.
+    fn expand(&mut self) -> StepOutput<'a> {
+        let name = match reference_name(&self.original[self.walker.post..]) {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        let before_at = self.cursor.move_to(self.walker.prev);
+        let reference = self.cursor.span_to(self.walker.post + "{}".len() + name.len());
+        for _ in 0..reference.len() - 1 {
+            self.walker.next(); // Skip to the '}'
+        }
+        self.cursor.move_to(self.walker.post);
+
+        let value = match self.variables.iter_mut().find(|variable| variable.name == name) {
+            Some(variable) => {
+                variable.is_used = true;
+                variable.value
+            }
+            None => {
+                let reference = &self.original[reference];
+                return Err(MarkupError::from_str(self.original, reference, ErrorKind::UndefinedVariable));
+            }
+        };
+
+        let before = &self.original[before_at];
+        match self.state {
+            State::Head | State::HBrackets => {
+                let is_choice = matches!(self.state, State::HBrackets);
+                let lexeme = if is_choice { self.emit_h_choice(before) } else { self.emit_head(before) };
+                self.queue(lexeme)?;
+                for word in head_words(value) {
+                    let lexeme = match (is_choice, word) {
+                        (false, ";") => self.emit_h_chord(word),
+                        (true, ";") => self.emit_hc_chord(word),
+                        (false, _) => self.emit_head(word),
+                        (true, _) => self.emit_h_choice(word),
+                    };
+                    self.queue(lexeme)?;
+                }
+            }
+            State::Body => {
+                let lexeme = self.emit_body(before);
+                self.queue(lexeme)?;
+                let lexeme = self.emit_body(value);
+                self.queue(lexeme)?;
+            }
+            State::BBrackets => {
+                let lexeme = self.emit_b_choice(before);
+                self.queue(lexeme)?;
+                let lexeme = self.emit_b_choice(value);
+                self.queue(lexeme)?;
+            }
+            State::BEscape | State::BLiteral => unreachable!("References are not expanded in escapes or literals"),
+        }
+        Ok(None)
+    }
+
+    fn queue(&mut self, lexeme: StepOutput<'a>) -> Output<()> {
+        if let Some(lexeme) = lexeme? {
+            self.queued.push(lexeme);
+        }
+        Ok(())
+    }
 }
 
 /******************************************************************************
@@ -564,6 +905,17 @@ impl<'a> Fsm<'a> {
         }))
     }
 
+    // A '-' or '<' just after the closing '|'/'!' is not part of the command
+    fn read_trim(&mut self) {
+        self.trim = match self.walker.peek() {
+            Some('-') => Trim::Strip,
+            Some('<') => Trim::Dedent,
+            _ => return,
+        };
+        self.walker.next();
+        self.cursor.move_to(self.walker.post);
+    }
+
     #[inline]
     fn mark_body_start(&mut self, lexeme_count: usize) -> Output<()> {
         self.entry_body_index = lexeme_count;
@@ -581,7 +933,7 @@ impl<'a> Fsm<'a> {
             Err(MarkupError::from_str(
                 self.original,
                 bar_to_bar,
-                errors::EMPTY_HOTKEY.to_string(),
+                ErrorKind::EmptyHotkey,
             ))
         } else {
             Ok(())
@@ -606,6 +958,7 @@ impl<'a> Fsm<'a> {
         // Only possibly equal after push for the last entry
         self.entry_stats.push(PostLexEntry {
             is_placeholder: self.is_placeholder,
+            trim: self.trim,
             head_size: s.0,
             body_size: s.1,
 
@@ -619,6 +972,7 @@ impl<'a> Fsm<'a> {
         //println!("{:?}", self.entry_stats.last().unwrap().tail);
 
         self.is_placeholder = next_is_placeholder;
+        self.trim = Trim::Keep;
         self.entry_head_index = lexeme_count;
         self.max_permutes = (1, 1);
         self.chord_count = (0, 0);
@@ -690,9 +1044,9 @@ impl<'a> CharsWithIndex<'a> {
         }
     }
 
-    fn error_at_current(&self, msg: &str) -> StepOutput<'a> {
+    fn error_at_current(&self, kind: ErrorKind) -> StepOutput<'a> {
         let current = &self.orig[self.prev..self.post];
-        Err(MarkupError::from_str(self.orig, current, msg.to_string()))
+        Err(MarkupError::from_str(self.orig, current, kind))
     }
 
     //fn fmt_err(&self, msg: &str) -> String {
@@ -780,3 +1134,87 @@ fn chars_with_index() {
     // TODO: test peek and eat_whitespace
     //let mut iter = CharsWithIndex::new("你m好!!我");
 }
+
+#[test]
+fn variables_are_expanded() {
+    use crate::parser::{parse_to_shortcuts, parse_to_shortcuts_allowing, parse_to_shortcuts_recover};
+
+    let owner = parse_to_shortcuts(
+        "@let mod = super shift\n\
+        @let term = alacritty -e\n\
+        |@{mod} a ; b| @{term} tmux\n\
+        @let side = left\n\
+        |@{mod} {{c, d}}| echo {{@{side},right}}\n",
+    )
+    .unwrap();
+    let shell = crate::Format::from_str("shell", None).unwrap().pipe_to_string(&owner).unwrap();
+    assert!(shell.contains(";; 'shift super a ; b')   alacritty -e tmux\n"), "{}", shell);
+    assert!(shell.contains(";; 'shift super c')   echo left\n"), "{}", shell);
+    assert!(shell.contains(";; 'shift super d')   echo right\n"), "{}", shell);
+
+    let kinds = |input| {
+        parse_to_shortcuts_recover(input)
+            .map(|_| Vec::new())
+            .unwrap_or_else(|errors| errors.iter().map(|err| err.kind()).collect())
+    };
+    assert_eq!(kinds("|a| @{b}\n"), [ErrorKind::UndefinedVariable]);
+    assert_eq!(kinds("@let b = 1\n|a| echo\n"), [ErrorKind::UnusedVariable]);
+    assert_eq!(kinds("@let b = 1\n@let b = 2\n|a| @{b}\n"), [ErrorKind::RedefinedVariable]);
+    assert_eq!(kinds("@let b\n|a| echo\n"), [ErrorKind::InvalidDefinition]);
+    assert_eq!(kinds("# comment\n@let b = 1\n"), [ErrorKind::NoEntries]);
+    // Recovering from an error still reads the definitions after it
+    assert_eq!(kinds("|super {{a| x\n@let v = 1\n|c| @{v}\n"), [ErrorKind::HeadInvalidClose]);
+    // Not a variable, so left as is
+    assert_eq!(kinds("|a| git rebase @{1}\n@lets\n"), []);
+    assert_eq!(kinds("|super g| git log @{u}\n"), [ErrorKind::UndefinedVariable]);
+    let owner = parse_to_shortcuts("|super {{f,g}}| git log \\@{u} {{\\@{push},\\,}}\\@\n").unwrap();
+    let shell = crate::Format::from_str("shell", None).unwrap().pipe_to_string(&owner).unwrap();
+    assert!(shell.contains(";; 'super f')   git log @{u} @{push}\\@\n"), "{}", shell);
+    assert!(shell.contains(";; 'super g')   git log @{u} ,\\@\n"), "{}", shell);
+    // Only errors that leave the shortcuts intact can be allowed
+    assert!(parse_to_shortcuts_allowing("@let b = 1\n|a| echo\n", |_| true).is_ok());
+    assert!(parse_to_shortcuts_allowing("@let b = 1\n|a| @{c}\n", |_| true).is_err());
+
+    // Errors in the value point at the definition
+    let err = parse_to_shortcuts("@let mod = super shfit\n|@{mod} a| echo\n").unwrap_err();
+    assert!(err.to_string().contains("1 | @let mod = super shfit\n  |                  ^^^^^\n"), "{}", err);
+
+    // But spans over a whole hotkey stay where it is used
+    let err = parse_to_shortcuts("@let mod = super\n|@{mod} a| 1\n|@{mod} a| 2\n").unwrap_err();
+    assert!(err.to_string().starts_with("error[CS0019]\n  |\n2 | |@{mod} a| 1\n  |         - first"), "{}", err);
+}
+
+#[test]
+fn literal_groups() {
+    use super::{parse_to_shortcuts, parse_to_shortcuts_recover};
+
+    let owner = parse_to_shortcuts(
+        "|super a| cat <<EOF{{{\n\
+        |a| is not a hotkey, @{b} is not a variable\n\
+        # and this is not a comment\n\
+        @let neither = a definition\n\
+        }}}\nEOF\n\
+        |super {{b, c}}| printf {{x, {{{'%s, %s' 1 2}}}}}\n",
+    )
+    .unwrap();
+    let shell = crate::Format::from_str("shell", None).unwrap().pipe_to_string(&owner).unwrap();
+    assert!(
+        shell.contains(
+            ";; 'super a')   cat <<EOF\n|a| is not a hotkey, @{b} is not a variable\n\
+            # and this is not a comment\n@let neither = a definition\n\nEOF\n"
+        ),
+        "{}",
+        shell
+    );
+    assert!(shell.contains(";; 'super b')   printf x\n"), "{}", shell);
+    assert!(shell.contains(";; 'super c')   printf  '%s, %s' 1 2\n"), "{}", shell);
+
+    // Only three brackets start a literal, the rest are output
+    let owner = parse_to_shortcuts("|a| echo {{{{a}}}}\n").unwrap();
+    let shell = crate::Format::from_str("shell", None).unwrap().pipe_to_string(&owner).unwrap();
+    assert!(shell.contains(";; 'a')   echo {a}\n"), "{}", shell);
+
+    let err = parse_to_shortcuts_recover("|a| echo {{{\n|b| c\n").unwrap_err();
+    assert_eq!(err[0].kind(), ErrorKind::EndBeforeLiteralClose);
+    assert!(err[0].to_string().contains("1 | |a| echo {{{\n  |          --- literal opened here\n"), "{}", err[0]);
+}
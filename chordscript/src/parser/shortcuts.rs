@@ -3,10 +3,10 @@
 
 use crate::constants::KEYCODES;
 use crate::constants::MODIFIERS;
-use crate::errors::parser as errors;
+use crate::errors::{parser as errors, ErrorKind};
 use crate::reporter::MarkupError;
 
-use super::{Chord, Shortcut, WithSpan};
+use super::{hotkey_span, Chord, Shortcut, Trim, WithSpan};
 use super::lexemes::{LexOutput, Lexeme, PostLexEntry};
 
 use std::ops::Range;
@@ -15,13 +15,14 @@ type Output<T> = Result<T, MarkupError>;
 #[derive(Debug)]
 pub struct ShortcutOwner<'filestr> {
     pub chords: Vec<Chord<'filestr>>,
-    scripts: Vec<WithSpan<'filestr, ()>>,
+    scripts: Vec<WithSpan<'filestr, bool>>,
     pub shortcuts: Vec<ShortcutPointer>,
 }
 
 #[derive(Clone, Debug)]
 pub struct ShortcutPointer {
     is_placeholder: bool,
+    trim: Trim,
     head: Range<usize>,
     body: Range<usize>,
 }
@@ -42,6 +43,7 @@ impl<'filestr> ShortcutOwner<'filestr> {
             is_placeholder: pointer.is_placeholder,
             hotkey: &self.chords[pointer.head.start..pointer.head.end],
             command: &self.scripts[pointer.body.start..pointer.body.end],
+            trim: pointer.trim,
         })
     }
 }
@@ -49,29 +51,48 @@ impl<'filestr> ShortcutOwner<'filestr> {
 /******************************************************************************
  * Main Parse Workflow
  ******************************************************************************/
-// Sorting is necessary for 'verify_no_overlap()'
+// Sorting is necessary for 'overlaps()'
 pub fn parse(input: LexOutput) -> Output<ShortcutOwner> {
-    let mut owner = parse_main(input)?;
+    let mut owner = parse_main(input, false).map_err(first)?;
     owner.sort();
-    verify_no_overlap(&owner)?;
+    overlaps(&owner).next().map_or(Ok(()), Err)?;
     Ok(owner)
 }
 
-// Although sorting is necessary for `verify_no_overlap()`, this will return
+// Although sorting is necessary for `overlaps()`, this will return
 // the original order of the shortcuts. Good for debugging.
 pub fn parse_unsorted(input: LexOutput) -> Output<ShortcutOwner> {
-    let mut owner = parse_main(input)?;
+    parse_unsorted_main(input, false).map_err(first)
+}
+
+// Same as 'parse_unsorted()' but reports every error instead of just the
+// first. Overlaps are only checked when every entry parses.
+pub fn parse_unsorted_recover(input: LexOutput) -> Result<ShortcutOwner, Vec<MarkupError>> {
+    parse_unsorted_main(input, true)
+}
+
+fn parse_unsorted_main(input: LexOutput, recover: bool) -> Result<ShortcutOwner, Vec<MarkupError>> {
+    let mut owner = parse_main(input, recover)?;
     let original_order = owner.shortcuts.clone();
     owner.sort();
-    verify_no_overlap(&owner)?;
-    Ok(ShortcutOwner {
-        chords: owner.chords,
-        scripts: owner.scripts,
-        shortcuts: original_order,
-    })
+    let errors = overlaps(&owner).take(if recover { usize::MAX } else { 1 }).collect::<Vec<_>>();
+    if errors.is_empty() {
+        Ok(ShortcutOwner {
+            chords: owner.chords,
+            scripts: owner.scripts,
+            shortcuts: original_order,
+        })
+    } else {
+        Err(errors)
+    }
+}
+
+// For when not recovering, there is only ever one error
+fn first(mut errors: Vec<MarkupError>) -> MarkupError {
+    errors.swap_remove(0)
 }
 
-fn parse_main(input: LexOutput) -> Output<ShortcutOwner> {
+fn parse_main(input: LexOutput, recover: bool) -> Result<ShortcutOwner, Vec<MarkupError>> {
     //println!("{:#?}", input.entry_stats);
     let (permutation_count, head_aggregate_size, body_aggregate_size) =
         input.entry_stats.iter().fold((0, 0, 0), |(a, b, c), s| {
@@ -82,7 +103,7 @@ fn parse_main(input: LexOutput) -> Output<ShortcutOwner> {
     let mut chords = vec![Chord::new(input.original); head_aggregate_size];
     let mut scripts = vec![
         WithSpan {
-            data: (),
+            data: false,
             context: input.original,
             source: &input.original[0..0],
         };
@@ -91,6 +112,7 @@ fn parse_main(input: LexOutput) -> Output<ShortcutOwner> {
     let mut shortcuts = vec![
         ShortcutPointer {
             is_placeholder: false,
+            trim: Trim::Keep,
             head: 0..0,
             body: 0..0,
         };
@@ -135,7 +157,7 @@ fn parse_main(input: LexOutput) -> Output<ShortcutOwner> {
 
     // Parse the lexeme stream
     let lexemes = &input.lexemes;
-    input
+    let errors = input
         .entry_stats
         .iter()
         .zip(slice_holder)
@@ -153,8 +175,12 @@ fn parse_main(input: LexOutput) -> Output<ShortcutOwner> {
         //    }
         //    result
         //})
-        .find(Result::is_err)
-        .unwrap_or(Ok((&[], &[])))?;
+        .filter_map(Result::err)
+        .take(if recover { usize::MAX } else { 1 })
+        .collect::<Vec<MarkupError>>();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
 
     //chords.iter().for_each(|a| println!("{:?}", a.sources));
     //scripts.iter().for_each(|a| println!("{:?}", a.sources));
@@ -174,7 +200,7 @@ fn parse_main(input: LexOutput) -> Output<ShortcutOwner> {
 
 // Verify that all hotkeys are accessible (and no duplicates)
 // e.g. 'super + a' and 'super + a; super + b' cannot be used at the same time
-fn verify_no_overlap(sorted_shortcuts: &ShortcutOwner) -> Output<()> {
+fn overlaps<'a>(sorted_shortcuts: &'a ShortcutOwner) -> impl Iterator<Item = MarkupError> + 'a {
     // Check 'sorted_shortcuts' is actually sorted
     debug_assert!(
         {
@@ -191,40 +217,42 @@ fn verify_no_overlap(sorted_shortcuts: &ShortcutOwner) -> Output<()> {
         "DEV: You forgot to sort the array"
     );
 
+    // On a conflict, keep 'prev' so that it is compared against everything
+    // it is a prefix of
     let mut iter = sorted_shortcuts.to_iter();
-    if let Some(first) = iter.next() {
-        iter.try_fold(first, |prev, curr| {
-            let prev_len = prev.hotkey.len();
-            let curr_len = curr.hotkey.len();
-            //println!("{} {}", prev_len, curr_len);
-            //println!("{:?}", curr.hotkey[0].sources);//, curr.hotkey[1].sources);
-            if prev_len <= curr_len && prev.hotkey == &curr.hotkey[0..prev_len] {
-                let prev_hotkey = prev
-                    .hotkey
-                    .iter()
-                    .map(|chord| chord.sources.join(" "))
-                    .collect::<Vec<_>>()
-                    .join(" ; ");
-
-                let curr_hotkey = prev
-                    .hotkey
-                    .iter()
-                    .map(|chord| chord.sources.join(" "))
-                    .collect::<Vec<_>>()
-                    .join(" ; ");
-                //MarkupError::from_str()
-                todo!(
-                    "have not created span errors yet\n\
-                    {}\nconflicts with\n{}",
-                    prev_hotkey,
-                    curr_hotkey
-                );
-            } else {
-                Ok(curr)
-            }
-        })?;
-    }
-    Ok(())
+    let first = iter.next();
+    iter.scan(first, |prev, curr| {
+        let prev_shortcut = prev.as_ref().unwrap();
+        let prev_len = prev_shortcut.hotkey.len();
+        if prev_len <= curr.hotkey.len() && prev_shortcut.hotkey == &curr.hotkey[0..prev_len] {
+            Some(Some(overlap_error(prev_shortcut, &curr)))
+        } else {
+            *prev = Some(curr);
+            Some(None)
+        }
+    })
+    .flatten()
+}
+
+// 'prev' is either the same hotkey as 'curr' or a prefix of it
+fn overlap_error(prev: &Shortcut, curr: &Shortcut) -> MarkupError {
+    let context = curr.hotkey[0].context;
+    let prev_span = hotkey_span(context, prev.hotkey);
+    let curr_span = hotkey_span(context, curr.hotkey);
+
+    // Point at the later duplicate, or at the hotkey that cannot be reached
+    let (kind, label, (primary, secondary)) = if prev.hotkey.len() == curr.hotkey.len() {
+        let in_file_order = if prev_span.as_ptr() <= curr_span.as_ptr() {
+            (curr_span, prev_span)
+        } else {
+            (prev_span, curr_span)
+        };
+        (ErrorKind::HotkeyDuplicate, errors::HOTKEY_FIRST_DEFINED, in_file_order)
+    } else {
+        (ErrorKind::HotkeyUnreachable, errors::HOTKEY_RECOGNISED_FIRST, (curr_span, prev_span))
+    };
+    MarkupError::from_str(context, primary, kind)
+        .with_label(context, secondary, label.to_string())
 }
 
 /******************************************************************************
@@ -236,7 +264,7 @@ struct ThreadLocalStorage<'a, 'filestr> {
     head_index: usize,
     body_index: usize,
     head: &'a mut [Chord<'filestr>],
-    body: &'a mut [WithSpan<'filestr, ()>],
+    body: &'a mut [WithSpan<'filestr, bool>],
     shortcut: &'a mut [ShortcutPointer],
 }
 
@@ -247,35 +275,23 @@ fn parse_head_lex_into_chords<'filestr>(
     lexemes: &[Lexeme<'filestr>],
 ) -> Output<()> {
     let mut index = 0;
-    let mut keys_added = 0;
     for i in 0..stats.permutations {
         let start = index;
 
         for lexeme in lexemes {
             let chord = &mut storage.head[index];
             match lexeme {
-                Lexeme::Key(k) => {
-                    chord.add(k, keys_added)?;
-                    keys_added += 1;
-                }
-                Lexeme::HChoice(choice, k) if *choice == i => {
-                    chord.add(k, keys_added)?;
-                    keys_added += 1;
-                }
-                Lexeme::ChordDelimH(_) => {
-                    index += 1;
-                    keys_added = 0;
-                }
-                Lexeme::ChordDelimHC(choice, _) if *choice == i => {
-                    index += 1;
-                    keys_added = 0;
-                }
+                Lexeme::Key(k) => chord.add(k, lexemes)?,
+                Lexeme::HChoice(choice, k) if *choice == i => chord.add(k, lexemes)?,
+                Lexeme::ChordDelimH(_) => index += 1,
+                Lexeme::ChordDelimHC(choice, _) if *choice == i => index += 1,
                 Lexeme::HChoice(_, _) | Lexeme::ChordDelimHC(_, _) => {}
                 _ => unreachable!("{:?}", lexeme),
             }
         }
         let base = storage.head_index;
         storage.shortcut[i].is_placeholder = stats.is_placeholder;
+        storage.shortcut[i].trim = stats.trim;
         storage.shortcut[i].head = (base + start)..(base + index);
     }
     Ok(())
@@ -295,10 +311,12 @@ fn parse_body_lex_into_scripts<'a, 'filestr>(
             match lexeme {
                 Lexeme::Literal(s) => {
                     frag.source = s;
+                    frag.data = false;
                     index += 1;
                 }
                 Lexeme::BChoice(choice, s) if *choice == i => {
                     frag.source = s;
+                    frag.data = true;
                     index += 1;
                 }
                 Lexeme::BChoice(_, _) => {}
@@ -311,20 +329,18 @@ fn parse_body_lex_into_scripts<'a, 'filestr>(
 }
 
 impl<'filestr> Chord<'filestr> {
-    fn add(&mut self, key: &'filestr str, keys_added: usize) -> Output<()> {
+    // 'lexemes' are the entry's head, for explaining clashes that only
+    // happen in some of the permutations
+    fn add(&mut self, key: &'filestr str, lexemes: &[Lexeme<'filestr>]) -> Output<()> {
         //println!("{}", self);
         if let Some(m) = MODIFIERS.iter().position(|m| *m == key) {
             let as_flag = 1 << m;
             if self.chord.modifiers & as_flag == 0 {
                 self.chord.modifiers |= as_flag;
-                self.sources[keys_added + 1] = key;
+                self.sources[m + 1] = key;
                 Ok(())
             } else {
-                Err(MarkupError::from_str(
-                    self.context,
-                    key,
-                    "Modifier already used".into(),
-                ))
+                Err(self.clash_error(key, self.sources[m + 1], ErrorKind::ModifierDuplicate, lexemes))
             }
         //println!("{}", as_flag);
         } else if let Some(k) = KEYCODES.iter().position(|k| *k == key) {
@@ -333,18 +349,102 @@ impl<'filestr> Chord<'filestr> {
                 self.sources[0] = key;
                 Ok(())
             } else {
-                Err(MarkupError::from_str(
-                    self.context,
-                    key,
-                    "Key already used".into(),
-                ))
+                Err(self.clash_error(key, self.sources[0], ErrorKind::KeyDuplicate, lexemes))
             }
         } else {
-            Err(MarkupError::from_str(
-                self.context,
-                key,
-                errors::INVALID_KEY.to_string(),
-            ))
+            Err(MarkupError::from_str(self.context, key, ErrorKind::InvalidKey))
+        }
+    }
+
+    fn clash_error(
+        &self,
+        key: &'filestr str,
+        earlier: &'filestr str,
+        kind: ErrorKind,
+        lexemes: &[Lexeme<'filestr>],
+    ) -> MarkupError {
+        let is_choice = |source: &str| {
+            lexemes.iter().any(|lexeme| {
+                matches!(lexeme, Lexeme::HChoice(_, s) if s.as_ptr() == source.as_ptr())
+            })
+        };
+        let error = MarkupError::from_str(self.context, key, kind).with_label(
+            self.context,
+            earlier,
+            errors::ALREADY_USED.to_string(),
+        );
+        if is_choice(key) || is_choice(earlier) {
+            error.with_note(errors::PERMUTATION_CLASH)
+        } else {
+            error
         }
     }
 }
+
+#[test]
+fn overlap_points_at_both() {
+    use super::parse_to_shortcuts;
+
+    let err = parse_to_shortcuts("|super a| echo 1\n|super b| echo 2\n|super  a| echo 3\n")
+        .unwrap_err()
+        .to_string();
+    assert_eq!(
+        err,
+        "error[CS0019]\n  |\n\
+        1 | |super a| echo 1\n  |  ------- first defined here\n\
+        3 | |super  a| echo 3\n  |  ^^^^^^^^\n  = This hotkey is defined previously.\n"
+    );
+
+    let err = parse_to_shortcuts("|super a ; super b| echo 1\n|super a| echo 2\n")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("1 | |super a ; super b| echo 1\n  |  ^^^^^^^^^^^^^^^^^\n"), "{}", err);
+    assert!(err.contains("2 | |super a| echo 2\n  |  ------- recognised first\n"), "{}", err);
+}
+
+#[test]
+fn recovers_every_error() {
+    use super::parse_to_shortcuts_recover;
+
+    let errors = parse_to_shortcuts_recover(
+        "|super a| echo 1\n\
+        |super {{b, c| echo 2\n\
+        |super foo| echo 3\n\
+        |super d| echo {{1,2}}\n\
+        |super e| echo 4\n\
+        |super f| echo {{5\n",
+    )
+    .unwrap_err();
+    let rows = errors
+        .iter()
+        .map(|err| err.to_string().lines().nth(2).unwrap()[0..1].to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(rows, ["2", "3", "4", "6"]);
+
+    // Overlaps are still found when every entry lexes and parses
+    let errors = parse_to_shortcuts_recover("|super a| 1\n|super b| 2\n|super a| 3\n|super b| 4\n")
+        .unwrap_err();
+    assert_eq!(errors.len(), 2);
+}
+
+#[test]
+fn chord_clash_points_at_both() {
+    use super::parse_to_shortcuts;
+
+    let err = parse_to_shortcuts("|super {{super, alt}} a| echo\n").unwrap_err().to_string();
+    assert_eq!(
+        err,
+        format!(
+            "error[CS0017]\n  |\n\
+            1 | |super {{{{super, alt}}}} a| echo\n\
+            \x20 |  ----- already used here\n\
+            \x20 |          ^^^^^\n\
+            \x20 = {}\n{}\n",
+            ErrorKind::ModifierDuplicate.message(),
+            errors::PERMUTATION_CLASH,
+        )
+    );
+
+    // Used to index out of bounds with four modifiers after the key
+    assert!(parse_to_shortcuts("|a super alt ctrl shift| echo\n").is_ok());
+}
@@ -24,8 +24,9 @@ use unicode_width::UnicodeWidthStr;
 
 use std::{error, fmt};
 
+use crate::errors::ErrorKind;
 use crate::sidebyside_len_and_push;
-use crate::templates::{Consumer, PreallocLen, PreallocPush};
+use crate::templates::{Consumer, Escape, PreallocLen, PreallocPush};
 
 //const DISPLAY_LIMIT: usize = 20;
 //const ELLIPSIS: &str = " ..."; // If we decide to column limit
@@ -53,6 +54,16 @@ use crate::templates::{Consumer, PreallocLen, PreallocPush};
 #[derive(Debug)]
 pub struct MarkupError {
     source: String,
+    range: (usize, usize),
+    kind: ErrorKind,
+    message: String,
+    labels: Vec<Label>,
+    source_map: Option<Box<SourceMap>>, // Boxed to keep 'Result's small
+}
+
+// A secondary span, e.g. where a hotkey was first defined
+#[derive(Debug)]
+struct Label {
     range: (usize, usize),
     message: String,
 }
@@ -60,14 +71,64 @@ pub struct MarkupError {
 impl error::Error for MarkupError {}
 
 impl MarkupError {
-    pub fn from_str<'a>(context: &'a str, span: &'a str, message: String) -> Self {
-        let index = (span.as_ptr() as usize) - (context.as_ptr() as usize);
+    pub fn from_str<'a>(context: &'a str, span: &'a str, kind: ErrorKind) -> Self {
         Self {
             source: context.to_string(),
-            range: (index, index + span.len()),
-            message,
+            range: range_of(context, span),
+            kind,
+            message: kind.message().to_string(),
+            labels: Vec::new(),
+            source_map: None,
         }
     }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    // Extra explanation specific to this instance of the error
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.message.push('\n');
+        self.message.push_str(note);
+        self
+    }
+
+    // For sorting errors by where they appear in the file
+    pub fn start(&self) -> usize {
+        self.range.0
+    }
+
+    // The text the error points at
+    pub fn span(&self) -> &str {
+        &self.source[self.range.0..self.range.1]
+    }
+
+    // 'context' must be the same as the one given to `from_str()`
+    pub fn with_label<'a>(mut self, context: &'a str, span: &'a str, message: String) -> Self {
+        debug_assert_eq!(self.source, context);
+        self.labels.push(Label {
+            range: range_of(context, span),
+            message,
+        });
+        self
+    }
+}
+
+impl MarkupError {
+    // For when 'context' is several files spliced together, see `SourceMap`
+    pub fn with_source_map(mut self, source_map: &SourceMap) -> Self {
+        self.source_map = Some(Box::new(source_map.clone()));
+        self
+    }
+
+    fn locate(&self, index: usize) -> Option<Location<'_>> {
+        self.source_map.as_ref()?.locate(&self.source, index)
+    }
+}
+
+fn range_of(context: &str, span: &str) -> (usize, usize) {
+    let index = (span.as_ptr() as usize) - (context.as_ptr() as usize);
+    (index, index + span.len())
 }
 
 //#[test]
@@ -96,21 +157,142 @@ impl fmt::Display for MarkupError {
         //let buffer = &mut String::with_capacity(self.string_len());
         //self.push_string_into(buffer);
         //f.write_str(buffer.as_str())
-        self.pipe((), f);
+        self.pipe(OutputType::Plain, f);
 
         Ok(())
     }
 }
 
+impl MarkupError {
+    pub fn render(&self, output: OutputType) -> String {
+        let mut buffer = String::with_capacity(self.len(output));
+        self.pipe(output, &mut buffer);
+        buffer
+    }
+}
+
+/****************************************************************************
+ * Files
+ ****************************************************************************/
+// Which file each part of a context came from, when '@include' splices files
+// together. Without any files, errors are reported as if there were only the
+// one unnamed file.
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    files: Vec<String>,
+    segments: Vec<Segment>,
+}
+
+// Segments always start at the beginning of a line
+#[derive(Clone, Debug)]
+struct Segment {
+    start: usize, // Byte in the context
+    file: usize,
+    row: usize,  // 1-indexed
+    byte: usize, // Byte in the file
+}
+
+struct Location<'a> {
+    file: &'a str,
+    row: usize,
+    byte: usize,
+}
+
+impl SourceMap {
+    // For a context that is entirely one file
+    pub fn single(path: &str) -> Self {
+        let mut source_map = Self::default();
+        let file = source_map.add_file(path);
+        source_map.add_segment(0, file, 1, 0);
+        source_map
+    }
+
+    // The first file is the one that includes the rest
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.files.iter().map(String::as_str)
+    }
+
+    // Returns the id for `add_segment()`
+    pub fn add_file(&mut self, path: &str) -> usize {
+        self.files.push(path.to_string());
+        self.files.len() - 1
+    }
+
+    // From byte 'start' of the context onwards is from 'file', starting at its
+    // byte 'byte' which is on row 'row'
+    pub fn add_segment(&mut self, start: usize, file: usize, row: usize, byte: usize) {
+        debug_assert!(self.segments.last().is_none_or(|last| last.start <= start));
+        self.segments.push(Segment { start, file, row, byte });
+    }
+
+    fn locate<'a>(&'a self, context: &str, index: usize) -> Option<Location<'a>> {
+        let segment = self.segments.iter().rev().find(|segment| segment.start <= index)?;
+        Some(Location {
+            file: &self.files[segment.file],
+            row: segment.row + context[segment.start..index].matches('\n').count(),
+            byte: segment.byte + index - segment.start,
+        })
+    }
+}
+
 /****************************************************************************
  *
  ****************************************************************************/
-type OutputType = ();
-//// @TODO: Add colour
-//enum OutputType {
-//    PosixShell,
-//    HTML,
-//}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputType {
+    Plain,
+    PosixShell, // ANSI escape codes for terminals
+    Html,       // <span> with classes, for the playground
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Style {
+    Primary,   // The header and '^'
+    Secondary, // '-' and its label
+    Gutter,    // Row numbers and the bars
+}
+
+const HTML_ESCAPE: Escape = Escape(&[('<', "&lt;"), ('>', "&gt;"), ('&', "&amp;")]);
+const NO_ESCAPE: Escape = Escape(&[]);
+
+impl OutputType {
+    fn open(self, style: Style) -> &'static str {
+        match (self, style) {
+            (Self::Plain, _) => "",
+            (Self::PosixShell, Style::Primary) => "\x1b[1;31m",
+            (Self::PosixShell, Style::Secondary) => "\x1b[1;34m",
+            (Self::PosixShell, Style::Gutter) => "\x1b[34m",
+            (Self::Html, Style::Primary) => "<span class=\"cs-primary\">",
+            (Self::Html, Style::Secondary) => "<span class=\"cs-secondary\">",
+            (Self::Html, Style::Gutter) => "<span class=\"cs-gutter\">",
+        }
+    }
+
+    fn close(self) -> &'static str {
+        match self {
+            Self::Plain => "",
+            Self::PosixShell => "\x1b[0m",
+            Self::Html => "</span>",
+        }
+    }
+
+    // Escapes for text taken from the source file or the messages
+    fn escape(self) -> Escape {
+        match self {
+            Self::Html => HTML_ESCAPE,
+            Self::Plain | Self::PosixShell => NO_ESCAPE,
+        }
+    }
+}
+
+impl Style {
+    fn marker(self) -> &'static str {
+        match self {
+            Self::Primary => "^",
+            Self::Secondary | Self::Gutter => "-",
+        }
+    }
+}
 
 impl PreallocLen<OutputType> for MarkupError {
     fn len(&self, extra: OutputType) -> usize {
@@ -123,82 +305,290 @@ impl<U: Consumer> PreallocPush<OutputType, U> for MarkupError {
     }
 }
 
-sidebyside_len_and_push!(error_len, error_pipe<U>(me: &MarkupError, _extra: OutputType, buffer: U) {
+sidebyside_len_and_push!(error_len, error_pipe<U>(me: &MarkupError, output: OutputType, buffer: U) {
     let context = me.source.as_str();
 
-    let mut iter = context.lines().enumerate().map(|(i, line)| {
-        ((line.as_ptr() as usize) - (context.as_ptr() as usize), i, line)
-    });
-    let (index, row, line) = iter.find(|(index, _, line)| me.range.0 < index + line.len() + "\n".len()).unwrap();
-    let start_row = ContextfulRow {
-        row_number: row,
-        highlight_index: me.range,
-        context_index: index,
-    };
-    let start_input = PaintInput {
-        row_number_max_len: count_digits(row),
-        line,
-        //word_wrap: 100,
-        message: &me.message,
+    // Spans are displayed in the order they appear in the file
+    let mut highlights = Vec::with_capacity(me.labels.len() + 1);
+    highlights.push((me.range, Style::Primary, ""));
+    highlights.extend(me.labels.iter().map(|label| (label.range, Style::Secondary, label.message.as_str())));
+    highlights.sort_by_key(|(range, _, _)| range.0);
+
+    let primary = me.locate(me.range.0);
+    let rows = highlights
+        .iter()
+        .flat_map(|(range, style, label)| rows_of(context, *range, *style, label))
+        .scan(0, |prev_row, mut row| {
+            // Spans on the same row share the printed line
+            row.show_line = *prev_row != row.row_number;
+            *prev_row = row.row_number;
+            Some(row)
+        })
+        .scan(primary.as_ref().map_or("", |location| location.file), |prev_file, mut row| {
+            if let Some(location) = me.locate(row.context_index) {
+                row.row_number = location.row;
+                row.file = location.file;
+                row.is_new_file = *prev_file != row.file;
+                *prev_file = row.file;
+            }
+            Some(row)
+        })
+        .collect::<Vec<_>>();
+    let row_number_max_len = rows.iter().map(|row| count_digits(row.row_number)).max().unwrap_or(1);
+    let gutter = &PADDING[0..row_number_max_len as usize];
+    let column = || {
+        let line_start = context[0..me.range.0].rfind('\n').map_or(0, |i| i + "\n".len());
+        context[line_start..me.range.0].chars().count() + 1
     };
 } {
-    start_row.len(start_input) => start_row.pipe(start_input, buffer);
+    output.open(Style::Primary).len() => buffer.consume(output.open(Style::Primary));
+    "error[";
+    me.kind.code().len() => buffer.consume(me.kind.code());
+    "]";
+    output.close().len() => buffer.consume(output.close());
+    "\n";
+    primary.as_ref().map_or(0, |location| {
+        gutter.len() + "--> ".len() + output.escape().len(location.file) + ":".len()
+            + count_digits(location.row) as usize + ":".len() + count_digits(column()) as usize + "\n".len()
+    }) => if let Some(location) = &primary {
+        buffer.consume(gutter);
+        buffer.consume("--> ");
+        output.escape().pipe(location.file, buffer);
+        buffer.consume(":");
+        push_num(location.row, buffer);
+        buffer.consume(":");
+        push_num(column(), buffer);
+        buffer.consume("\n");
+    };
+    styled_len(gutter, (output, Style::Gutter)) => styled_pipe(gutter, (output, Style::Gutter), buffer);
+    styled_len(" |", (output, Style::Gutter)) => styled_pipe(" |", (output, Style::Gutter), buffer);
+    "\n";
+    rows.iter().map(|row| row.len((row_number_max_len, output))).sum::<usize>() =>
+        rows.iter().for_each(|row| row.pipe((row_number_max_len, output), buffer));
 
+    styled_len(gutter, (output, Style::Gutter)) => styled_pipe(gutter, (output, Style::Gutter), buffer);
+    styled_len(" =", (output, Style::Gutter)) => styled_pipe(" =", (output, Style::Gutter), buffer);
+    " ";
+    output.escape().len(&me.message) => output.escape().pipe(&me.message, buffer);
+    "\n";
+});
 
-    // @TODO: Display second row
+// 'text' wrapped in the colour for 'style'
+sidebyside_len_and_push!(styled_len, styled_pipe<U>(text: &str, extra: (OutputType, Style), buffer: U) {
+    let (output, style) = extra;
+} {
+    output.open(style).len() => buffer.consume(output.open(style));
+    output.escape().len(text) => output.escape().pipe(text, buffer);
+    output.close().len() => buffer.consume(output.close());
 });
 
 /****************************************************************************
  * For printing a single row
  ****************************************************************************/
-struct PaintInput<'a> {
-    row_number_max_len: u8,
-    line: &'a str,
-    //word_wrap: usize,
-    message: &'a str,
-}
-
-struct ContextfulRow {
+struct ContextfulRow<'a> {
     row_number: usize,
+    line: &'a str,
     highlight_index: (usize, usize),
     context_index: usize,
+    style: Style,
+    label: &'a str, // Only on the last row of a span
+    show_line: bool,
+    file: &'a str,
+    is_new_file: bool, // Different from the row before, so print the name
 }
 
-impl ContextfulRow {
-    sidebyside_len_and_push!(len, pipe<U>(self: &Self, extra: PaintInput, buffer: U) {
-    } {
-        USIZE_BASE_10_MAX_DIGITS as usize => buffer.consume(&PADDING[0..extra.row_number_max_len as usize]);
-        " |\n";
+// Every row that 'range' touches. Splitting on '\n' (unlike `.lines()`)
+// keeps the empty row after a final newline, where end-of-file errors point.
+fn rows_of<'a>(
+    context: &'a str,
+    range: (usize, usize),
+    style: Style,
+    label: &'a str,
+) -> Vec<ContextfulRow<'a>> {
+    let mut rows = context
+        .split('\n')
+        .enumerate()
+        .map(|(i, line)| (range_of(context, line).0, i, line))
+        .filter(|(index, _, line)| {
+            range.0 < index + line.len() + "\n".len() && (range.0 >= *index || range.1 > *index)
+        })
+        .map(|(index, i, line)| ContextfulRow {
+            row_number: i + 1,
+            line: line.strip_suffix('\r').unwrap_or(line),
+            highlight_index: range,
+            context_index: index,
+            style,
+            label: "",
+            show_line: true,
+            file: "",
+            is_new_file: false,
+        })
+        .collect::<Vec<_>>();
+    if let Some(last) = rows.last_mut() {
+        last.label = label;
+    }
+    rows
+}
 
-        USIZE_BASE_10_MAX_DIGITS as usize => push_num(self.row_number, buffer);
-        " | ";
-        extra.line.len() => buffer.consume(extra.line);
-        "\n";
+impl ContextfulRow<'_> {
+    sidebyside_len_and_push!(len, pipe<U>(self: &Self, extra: (u8, OutputType), buffer: U) {
+        let (row_number_max_len, output) = extra;
+        let line = self.line;
+        let gutter = &PADDING[0..row_number_max_len as usize];
+        let (open, close) = (output.open(self.style), output.close());
+    } {
+        row_number_max_len as usize + "::: ".len() + output.escape().len(self.file) + "\n".len()
+        => if self.is_new_file {
+            buffer.consume(gutter);
+            buffer.consume("::: ");
+            output.escape().pipe(self.file, buffer);
+            buffer.consume("\n");
+        };
+        output.open(Style::Gutter).len() + row_number_max_len as usize + " | ".len() + close.len()
+            + output.escape().len(line) + "\n".len()
+        => if self.show_line {
+            let padding = &PADDING[count_digits(self.row_number) as usize..row_number_max_len as usize];
+            buffer.consume(output.open(Style::Gutter));
+            buffer.consume(padding);
+            push_num(self.row_number, buffer);
+            buffer.consume(" | ");
+            buffer.consume(close);
+            output.escape().pipe(line, buffer);
+            buffer.consume("\n");
+        };
 
-        USIZE_BASE_10_MAX_DIGITS as usize => buffer.consume(&PADDING[0..extra.row_number_max_len as usize]);
-        " | ";
-        extra.line.width_cjk() => {
-            let offset = self.highlight_index.0.saturating_sub(self.context_index);
-            let offset_disp = extra.line[0..offset].width_cjk();
-            for _ in 0..offset_disp {
+        styled_len(gutter, (output, Style::Gutter)) => styled_pipe(gutter, (output, Style::Gutter), buffer);
+        styled_len(" | ", (output, Style::Gutter)) => styled_pipe(" | ", (output, Style::Gutter), buffer);
+        // At least one marker, even for an empty span
+        line.width_cjk() + 1 + open.len() + close.len() => {
+            let offset = self.highlight_index.0.saturating_sub(self.context_index).min(line.len());
+            let close_index = (self.highlight_index.1 - self.context_index).clamp(offset, line.len());
+            for _ in 0..line[0..offset].width_cjk() {
                 buffer.consume(" ");
             }
-
-            let len = extra.line.width_cjk();
-            let arrow_close = (self.highlight_index.1 - self.context_index - offset).min(len);
-            let arrow_disp = extra.line[offset..arrow_close].width_cjk();
-            for _ in 0..arrow_disp {
-                buffer.consume("^");
+            buffer.consume(open);
+            for _ in 0..line[offset..close_index].width_cjk().max(1) {
+                buffer.consume(self.style.marker());
             }
+            buffer.consume(close);
+        };
+        " ".len() + styled_len(self.label, (output, self.style)) => if !self.label.is_empty() {
+            buffer.consume(" ");
+            styled_pipe(self.label, (output, self.style), buffer);
         };
         "\n";
+    });
+}
 
-        USIZE_BASE_10_MAX_DIGITS as usize => buffer.consume(&PADDING[0..extra.row_number_max_len as usize]);
-        " = ";
-        extra.message.len() => buffer.consume(extra.message);
-        "\n";
+/****************************************************************************
+ * Machine-readable output
+ ****************************************************************************/
+// A JSON array of diagnostics for editors and CI. Byte offsets are 0-indexed
+// (the end is exclusive), lines and columns are 1-indexed and columns count
+// chars rather than bytes.
+pub struct JsonReport<'a> {
+    pub path: &'a str,
+    pub errors: &'a [MarkupError],
+}
 
-    });
+const JSON_ESCAPE: Escape = Escape(&[
+    ('"', "\\\""),
+    ('\\', "\\\\"),
+    ('\n', "\\n"),
+    ('\t', "\\t"),
+    ('\r', "\\r"),
+]);
+
+impl fmt::Display for JsonReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        json_pipe(self, (), f);
+        Ok(())
+    }
+}
+impl PreallocLen<()> for JsonReport<'_> {
+    fn len(&self, extra: ()) -> usize {
+        json_len(self, extra)
+    }
+}
+impl<U: Consumer> PreallocPush<(), U> for JsonReport<'_> {
+    fn pipe(&self, extra: (), buffer: &mut U) {
+        json_pipe(self, extra, buffer);
+    }
+}
+
+sidebyside_len_and_push!(json_len, json_pipe<U>(me: &JsonReport, _extra: (), buffer: U) {} {
+    "[";
+    me.errors.iter().map(|err| ",".len() + diagnostic_len(err, me.path)).sum::<usize>() => {
+        let mut delim = "";
+        me.errors.iter().for_each(|err| {
+            buffer.consume(delim);
+            diagnostic_pipe(err, me.path, buffer);
+            delim = ",";
+        });
+    };
+    "]";
+});
+
+sidebyside_len_and_push!(diagnostic_len, diagnostic_pipe<U>(me: &MarkupError, path: &str, buffer: U) {
+    let file_of = |index| me.locate(index).map_or(path, |location| location.file);
+} {
+    "{\"file\":\"";
+    JSON_ESCAPE.len(file_of(me.range.0)) => JSON_ESCAPE.pipe(file_of(me.range.0), buffer);
+    "\",\"severity\":\"error\",\"code\":\"";
+    me.kind.code().len() => buffer.consume(me.kind.code());
+    "\",\"message\":\"";
+    JSON_ESCAPE.len(&me.message) => JSON_ESCAPE.pipe(&me.message, buffer);
+    "\",";
+    SPAN_MAX_LEN => span_pipe(me, me.range, buffer);
+    ",\"labels\":[";
+    me.labels.iter().map(|label| {
+        "{\"file\":\"\",\"message\":\"\",},".len() + JSON_ESCAPE.len(file_of(label.range.0))
+            + JSON_ESCAPE.len(&label.message) + SPAN_MAX_LEN
+    }).sum::<usize>() => {
+        let mut delim = "";
+        me.labels.iter().for_each(|label| {
+            buffer.consume(delim);
+            buffer.consume("{\"file\":\"");
+            JSON_ESCAPE.pipe(file_of(label.range.0), buffer);
+            buffer.consume("\",\"message\":\"");
+            JSON_ESCAPE.pipe(&label.message, buffer);
+            buffer.consume("\",");
+            span_pipe(me, label.range, buffer);
+            buffer.consume("}");
+            delim = ",";
+        });
+    };
+    "]}";
+});
+
+// Positions are fixed-size apart from the numbers
+const SPAN_MAX_LEN: usize = "\"start\":,\"end\":".len() + 2 * POSITION_MAX_LEN;
+const POSITION_MAX_LEN: usize =
+    "{\"byte\":,\"line\":,\"column\":}".len() + 3 * USIZE_BASE_10_MAX_DIGITS;
+
+fn span_pipe<U: Consumer>(me: &MarkupError, range: (usize, usize), buffer: &mut U) {
+    buffer.consume("\"start\":");
+    position_pipe(me, range.0, buffer);
+    buffer.consume(",\"end\":");
+    position_pipe(me, range.1, buffer);
+}
+
+// Relative to the file that 'index' is in
+fn position_pipe<U: Consumer>(me: &MarkupError, index: usize, buffer: &mut U) {
+    let context = me.source.as_str();
+    let before = &context[0..index];
+    let line_start = before.rfind('\n').map_or(0, |i| i + "\n".len());
+    let (row, byte) = me.locate(index).map_or_else(
+        || (before.matches('\n').count() + 1, index),
+        |location| (location.row, location.byte),
+    );
+    buffer.consume("{\"byte\":");
+    push_num(byte, buffer);
+    buffer.consume(",\"line\":");
+    push_num(row, buffer);
+    buffer.consume(",\"column\":");
+    push_num(before[line_start..].chars().count() + 1, buffer);
+    buffer.consume("}");
 }
 
 /****************************************************************************
@@ -241,3 +631,53 @@ const fn count_digits(mut num: usize) -> u8 {
     }
     size
 }
+
+#[test]
+fn multiline_spans() {
+    let context = "|super a| echo {{1,\n  2}}\n|super b| echo 3\n";
+    let err = MarkupError::from_str(context, &context[27..34], ErrorKind::HotkeyDuplicate)
+        .with_label(context, &context[15..25], "spans two rows".to_string());
+    assert_eq!(
+        err.to_string(),
+        "error[CS0019]\n  |\n\
+        1 | |super a| echo {{1,\n  |                ----\n\
+        2 |   2}}\n  | ----- spans two rows\n\
+        3 | |super b| echo 3\n  |  ^^^^^^^\n  = This hotkey is defined previously.\n"
+    );
+}
+
+#[test]
+fn json_positions() {
+    let context = "|super a| echo\n|süper b| \"x\"\n";
+    let err = MarkupError::from_str(context, &context[19..20], ErrorKind::ModifierDuplicate)
+        .with_note("bad \"key\"")
+        .with_label(context, &context[1..6], "here".to_string());
+    let errors = [err];
+    let report = JsonReport { path: "wm-shortcuts", errors: &errors };
+    let mut buffer = String::with_capacity(report.len(()));
+    report.pipe((), &mut buffer);
+    assert_eq!(
+        buffer,
+        "[{\"file\":\"wm-shortcuts\",\"severity\":\"error\",\"code\":\"CS0017\",\
+        \"message\":\"Modifier already used\\nbad \\\"key\\\"\",\
+        \"start\":{\"byte\":19,\"line\":2,\"column\":4},\"end\":{\"byte\":20,\"line\":2,\"column\":5},\
+        \"labels\":[{\"file\":\"wm-shortcuts\",\"message\":\"here\",\
+        \"start\":{\"byte\":1,\"line\":1,\"column\":2},\"end\":{\"byte\":6,\"line\":1,\"column\":7}}]}]"
+    );
+}
+
+#[test]
+fn coloured_output() {
+    let context = "|super a| echo <b> && x\n";
+    let err = MarkupError::from_str(context, &context[15..18], ErrorKind::InvalidKey);
+
+    let ansi = err.render(OutputType::PosixShell);
+    assert!(ansi.starts_with("\x1b[1;31merror[CS0016]\x1b[0m\n"), "{}", ansi);
+    assert!(ansi.contains("\x1b[34m1 | \x1b[0m|super a| echo <b> && x\n"), "{}", ansi);
+    assert!(ansi.contains("              \x1b[1;31m^^^\x1b[0m\n"), "{}", ansi);
+
+    let html = err.render(OutputType::Html);
+    assert!(html.contains("|super a| echo &lt;b&gt; &amp;&amp; x\n"), "{}", html);
+    assert!(html.contains("<span class=\"cs-primary\">^^^</span>"), "{}", html);
+    assert!(!err.render(OutputType::Plain).contains('\x1b'));
+}
@@ -1,14 +1,48 @@
 //run: cargo test -- --nocapture
 
 use std::io;
+use std::ops::Range;
 
-use crate::constants::{KEY_UTF8_MAX_LEN, MOD_UTF8_MAX_LEN, KEYCODES, MODIFIERS};
-use crate::parser::{shortcuts::ShortcutOwner, Chord, InnerChord};
-use crate::{array_index_by_enum, sidebyside_len_and_push};
+use crate::constants::fold_max_len;
+use crate::errors::ErrorKind;
+use crate::parser::{shortcuts::ShortcutOwner, span_covering, Chord, Cursor, InnerChord, Shortcut, Trim, WithSpan};
+use crate::reporter::MarkupError;
+use crate::{array_index_by_enum, sidebyside_len_and_push, Format};
+
+// The 'Wrapper' the vtables point to, for a template's 'len' and 'pipe' made
+// with 'sidebyside_len_and_push!'. '$me' is what they get from the 'Format'.
+macro_rules! wrapper {
+    ($len:ident, $pipe:ident(|$format:pat_param| $me:expr)) => {
+        wrapper!(Wrapper: $len, $pipe(|$format| $me));
+    };
+    ($Wrapper:ident: $len:ident, $pipe:ident(|$format:pat_param| $me:expr)) => {
+        pub struct $Wrapper();
+        impl $crate::templates::PreallocLen<$crate::templates::Input<'_, '_>> for $Wrapper {
+            fn len(&self, (owner, $format): $crate::templates::Input) -> usize {
+                $len($me, owner)
+            }
+        }
+        impl<U: $crate::templates::Consumer> $crate::templates::PreallocPush<$crate::templates::Input<'_, '_>, U>
+            for $Wrapper
+        {
+            fn pipe(&self, (owner, $format): $crate::templates::Input, buffer: &mut U) {
+                $pipe($me, owner, buffer)
+            }
+        }
+    };
+}
 
 mod debug_shortcuts;
+mod hyprland;
+mod i3;
 mod i3_shell;
+mod leftwm;
 mod shellscript;
+mod sway;
+mod sxhkd;
+mod tmux;
+
+pub use shellscript::Fallback;
 
 //macro_rules! row {
 //    ($Enum:ident :: $Variant:ident => $id:literal) => {
@@ -22,17 +56,70 @@ pub enum F { // Format
     S(&'static str), // Shell
 }
 
+// The shortcuts, and the format for the options such as the command that
+// calls the shell runner (see 'shellscript.rs')
+pub type Input<'a, 'b> = (&'b ShortcutOwner<'a>, &'b Format<'b>);
+
+// Checks if the template can represent every shortcut before piping
+type Verify = for<'a, 'b> fn(&'b ShortcutOwner<'a>) -> Result<(), MarkupError>;
+
+// What a template can express, for listing in the cli. Placeholders are
+// always checked for clashes, the flag is for templates that output them.
+pub const MULTI_CHORD: u8 = 1 << 0;
+pub const MODES: u8 = 1 << 1;
+pub const PLACEHOLDERS: u8 = 1 << 2;
+pub const FEATURE_NAMES: [&str; 3] = ["multi-chord", "modes", "placeholders"];
+
 array_index_by_enum!( TEMPLATE_COUNT: usize
     pub enum Templates {
-        ShellScript    => F::N("shell")           => &shellscript::Wrapper()     => &shellscript::Wrapper(),
-        I3Shell        => F::S("i3")              => &i3_shell::Wrapper()        => &i3_shell::Wrapper(),
-        DebugShortcuts => F::N("debug-shortcuts") => &debug_shortcuts::Wrapper() => &debug_shortcuts::Wrapper(),
+        ShellScript    => F::N("shell")           => &shellscript::Wrapper()     => &shellscript::Wrapper()     => accept_all       => "Shellscript that runs the command for a hotkey, the runner for shell templates" => MULTI_CHORD,
+        I3             => F::N("i3")              => &i3::Wrapper()              => &i3::Wrapper()              => i3::verify       => "i3 config with the commands inlined (single-line commands only)"                  => MULTI_CHORD | MODES,
+        I3Shell        => F::S("i3")              => &i3_shell::Wrapper()        => &i3_shell::Wrapper()        => accept_all       => "i3 config that calls the shell runner"                                           => MULTI_CHORD | MODES,
+        Sway           => F::N("sway")            => &sway::Wrapper()            => &sway::Wrapper()            => i3::verify       => "sway config with the commands inlined (single-line commands only)"                => MULTI_CHORD | MODES,
+        SwayShell      => F::S("sway")            => &sway::ShellWrapper()       => &sway::ShellWrapper()       => accept_all       => "sway config that calls the shell runner"                                         => MULTI_CHORD | MODES,
+        Sxhkd          => F::N("sxhkd")           => &sxhkd::Wrapper()           => &sxhkd::Wrapper()           => sxhkd::verify    => "sxhkdrc, which chains chords itself (single-line commands only)"                  => MULTI_CHORD,
+        Hyprland       => F::N("hyprland")        => &hyprland::Wrapper()        => &hyprland::Wrapper()        => hyprland::verify => "Hyprland config, multi-chord hotkeys become submaps"                             => MULTI_CHORD | MODES,
+        LeftWm         => F::N("leftwm")          => &leftwm::Wrapper()          => &leftwm::Wrapper()          => leftwm::verify   => "LeftWM '[[keybind]]' entries (single-chord hotkeys only)"                        => 0,
+        Tmux           => F::N("tmux")            => &tmux::Wrapper()            => &tmux::Wrapper()            => tmux::verify     => "tmux config, multi-chord hotkeys become key tables"                              => MULTI_CHORD | MODES,
+        DebugShortcuts => F::N("debug-shortcuts") => &debug_shortcuts::Wrapper() => &debug_shortcuts::Wrapper() => accept_all       => "Every shortcut and placeholder with the permutations expanded"                   => MULTI_CHORD | PLACEHOLDERS,
     }
     => 1 pub const ID_TO_TYPE: [F]
-    => 2 pub const VTABLE_STRING: [&dyn for<'a, 'b> PreallocPush<&'b ShortcutOwner<'a>, String>]
-    => 3 pub const VTABLE_STDOUT: [&dyn for<'a, 'b> PreallocPush<&'b ShortcutOwner<'a>, io::Stdout>]
+    => 2 pub const VTABLE_STRING: [&dyn for<'a, 'b> PreallocPush<Input<'a, 'b>, String>]
+    => 3 pub const VTABLE_STDOUT: [&dyn for<'a, 'b> PreallocPush<Input<'a, 'b>, io::Stdout>]
+    => 4 pub const VTABLE_VERIFY: [Verify]
+    => 5 pub const DESCRIPTIONS: [&str]
+    => 6 pub const FEATURES: [u8]
 );
 
+fn accept_all(_: &ShortcutOwner) -> Result<(), MarkupError> {
+    Ok(())
+}
+
+// For configs that need every command on a single line
+fn verify_one_line(owner: &ShortcutOwner, kind: ErrorKind) -> Result<(), MarkupError> {
+    verify_commands(owner, kind, |s| trim_command(s.command).all(|frag| !frag.contains('\n')))
+}
+
+// Reports the first shortcut whose command is not 'is_valid'
+fn verify_commands<F: Fn(&Shortcut) -> bool>(
+    owner: &ShortcutOwner,
+    kind: ErrorKind,
+    is_valid: F,
+) -> Result<(), MarkupError> {
+    match owner.to_iter().filter(|s| !s.is_placeholder).find(|s| !is_valid(s)) {
+        Some(shortcut) => {
+            let context = shortcut.hotkey[0].context;
+            let command = shortcut.command.iter().map(|with_span| with_span.source);
+            Err(MarkupError::from_str(
+                context,
+                span_covering(context, command),
+                kind,
+            ))
+        }
+        None => Ok(()),
+    }
+}
+
 pub trait Consumer {
     fn consume(&mut self, part: &str);
 }
@@ -83,13 +170,6 @@ pub trait PreallocPush<T, U: Consumer>: PreallocLen<T> {
 //    }
 //}
 
-const DEBUG_CHORD_CONSTANTS: DeserialiseChord = DeserialiseChord {
-    delim: " ; ",
-    mod_to_str: &MODIFIERS,
-    key_to_str: &KEYCODES,
-};
-const CHORD_MAX_PUSH_LEN: usize = InnerChord::new().len(DEBUG_CHORD_CONSTANTS);
-
 #[derive(Clone, Copy)]
 pub(crate) struct DeserialiseChord {
     delim: &'static str,
@@ -100,7 +180,7 @@ pub(crate) struct DeserialiseChord {
 impl InnerChord {
     sidebyside_len_and_push!(! const ! len, pipe<F> (self: &Self, extra: DeserialiseChord, buffer: F) {} {
         // At most `mod_to_str.len()` modifiers will be added
-        extra.mod_to_str.len() * (extra.delim.len() + MOD_UTF8_MAX_LEN)
+        extra.mod_to_str.len() * (extra.delim.len() + fold_max_len(extra.mod_to_str))
         => {
             let mut delim = "";
             for (i, mod_str) in extra.mod_to_str.iter().enumerate() {
@@ -112,9 +192,11 @@ impl InnerChord {
             }
         };
 
-        // Zero or two &str added
-        extra.delim.len() + KEY_UTF8_MAX_LEN => if self.key < extra.key_to_str.len() {
-            buffer.consume(extra.delim);
+        // Zero, one, or two &str added (no delim if there are no modifiers)
+        extra.delim.len() + fold_max_len(extra.key_to_str) => if self.key < extra.key_to_str.len() {
+            if self.modifiers != 0 {
+                buffer.consume(extra.delim);
+            }
             buffer.consume(extra.key_to_str[self.key]);
         };
     });
@@ -128,17 +210,197 @@ impl InnerChord {
 struct DeserialiseHotkey<'a, 'b>(&'static str, &'b [Chord<'a>]);
 impl PreallocLen<DeserialiseChord> for DeserialiseHotkey<'_, '_> {
     fn len(&self, extra: DeserialiseChord) -> usize {
-        self.1.len() * (extra.delim.len() + CHORD_MAX_PUSH_LEN)
+        self.1.len() * (self.0.len() + InnerChord::new().len(extra))
     }
 }
 impl<U: Consumer> PreallocPush<DeserialiseChord, U> for DeserialiseHotkey<'_, '_> {
     fn pipe(&self, extra: DeserialiseChord, buffer: &mut U) {
         let mut delim = "";
         for sourced_chord in self.1 {
-            buffer.consume(delim);
             buffer.consume(delim);
             delim = self.0;
             sourced_chord.chord.pipe(extra, buffer);
         }
     }
 }
+
+// The fragments of a command with the whitespace at the start and end of the
+// entire command removed, e.g. the newline and indent after '|super a|'
+pub(crate) fn trim_command<'list, 'filestr>(
+    command: &'list [WithSpan<'filestr, bool>],
+) -> impl Iterator<Item = &'filestr str> + Clone + 'list {
+    strip(command, true)
+}
+
+fn strip<'list, 'filestr>(
+    command: &'list [WithSpan<'filestr, bool>],
+    is_stripped: bool,
+) -> impl Iterator<Item = &'filestr str> + Clone + 'list {
+    let range = stripped_range(command, is_stripped);
+    let (begin, close) = (range.start, range.end);
+    command[range]
+        .iter()
+        .enumerate()
+        .map(move |(i, with_span)| {
+            let frag = with_span.source;
+            let frag = if is_stripped && i == 0 { frag.trim_start() } else { frag };
+            if is_stripped && begin + i + 1 == close { frag.trim_end() } else { frag }
+        })
+}
+
+// The fragments that 'strip()' keeps, only the first and last are trimmed
+fn stripped_range(command: &[WithSpan<'_, bool>], is_stripped: bool) -> Range<usize> {
+    if is_stripped {
+        let begin = command
+            .iter()
+            .position(|s| !s.source.trim_start().is_empty())
+            .unwrap_or(command.len());
+        let close = command
+            .iter()
+            .rposition(|s| !s.source.trim_end().is_empty())
+            .map(|i| i + 1)
+            .unwrap_or(0)
+            .max(begin);
+        begin..close
+    } else {
+        0..command.len()
+    }
+}
+
+// The fragments of a command trimmed as the shortcut asks, for templates that
+// can output multi-line commands. The fragments are split at newlines.
+pub(crate) fn command_fragments<'list, 'filestr>(
+    shortcut: &Shortcut<'list, 'filestr>,
+) -> impl Iterator<Item = &'filestr str> + Clone + 'list {
+    let indent = match shortcut.trim {
+        Trim::Dedent => common_indent(shortcut.command),
+        Trim::Keep | Trim::Strip => 0,
+    };
+    Dedent {
+        fragments: strip(shortcut.command, shortcut.trim != Trim::Keep),
+        rest: "",
+        indent,
+        to_drop: 0,
+    }
+}
+
+// The least indent of the lines after the first, ignoring blank lines. The
+// first line is the rest of the line with the hotkey.
+fn common_indent(command: &[WithSpan<'_, bool>]) -> usize {
+    let mut indent = None;
+    let mut column = None; // Only 'Some' while in the indent of a line
+    for c in command.iter().flat_map(|with_span| with_span.source.chars()) {
+        match (c, column) {
+            ('\n', _) => column = Some(0),
+            (' ' | '\t', Some(n)) => column = Some(n + 1),
+            (_, Some(n)) => {
+                indent = Some(indent.map_or(n, |i: usize| i.min(n)));
+                column = None;
+            }
+            (_, None) => {}
+        }
+    }
+    indent.unwrap_or(0)
+}
+
+// Removes 'indent' blanks from the start of every line after the first. The
+// indent can be split over several fragments, e.g. by a permutation group.
+#[derive(Clone)]
+struct Dedent<'filestr, I> {
+    fragments: I,
+    rest: &'filestr str, // Of the fragment being split
+    indent: usize,
+    to_drop: usize, // Of the indent of the current line
+}
+
+impl<'filestr, I: Iterator<Item = &'filestr str>> Iterator for Dedent<'filestr, I> {
+    type Item = &'filestr str;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.rest.is_empty() {
+                self.rest = self.fragments.next()?;
+            }
+            let blanks = self
+                .rest
+                .bytes()
+                .take(self.to_drop)
+                .take_while(|b| *b == b' ' || *b == b'\t')
+                .count();
+            self.rest = &self.rest[blanks..];
+            // The indent continues into the next fragment only if this one ran out
+            self.to_drop = if self.rest.is_empty() { self.to_drop - blanks } else { 0 };
+
+            if !self.rest.is_empty() {
+                let (line, rest) = match self.rest.find('\n') {
+                    Some(i) => {
+                        self.to_drop = self.indent;
+                        self.rest.split_at(i + 1)
+                    }
+                    None => (self.rest, ""),
+                };
+                self.rest = rest;
+                return Some(line);
+            }
+        }
+    }
+}
+
+// Pushes 'frag' with the chars in the table replaced, e.g. '"' -> '\"'
+#[derive(Clone, Copy)]
+pub(crate) struct Escape(pub &'static [(char, &'static str)]);
+
+impl Escape {
+    fn lookup(&self, c: char) -> Option<&'static str> {
+        self.0.iter().find(|(from, _)| *from == c).map(|(_, into)| *into)
+    }
+
+    sidebyside_len_and_push!(pub(crate) len, pipe<U>(self: &Self, frag: &str, buffer: U) {} {
+        frag.chars().map(|c| self.lookup(c).map_or(c.len_utf8(), str::len)).sum::<usize>() => {
+            let mut cursor = Cursor(0);
+            for (i, c) in frag.char_indices() {
+                if let Some(escaped) = self.lookup(c) {
+                    buffer.consume(&frag[cursor.move_to(i)]);
+                    buffer.consume(escaped);
+                    cursor.move_to(i + c.len_utf8());
+                }
+            }
+            buffer.consume(&frag[cursor.0..]);
+        };
+    });
+}
+
+// Quotes 'word' for sh unless every char is safe as is, e.g. a path with a
+// space. 'escape' is for the config that the shell command is embedded in.
+fn is_shell_safe(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_-+=./,:@%~".contains(c)
+}
+
+sidebyside_len_and_push!(pub(crate) shell_quote_len, shell_quote_pipe<U>(word: &str, escape: Escape, buffer: U) {
+    // sh only expands '~' outside of quotes
+    let (home, word) = match word.strip_prefix("~/") {
+        Some(rest) => ("~/", rest),
+        None => ("", word),
+    };
+    let is_safe = !word.is_empty() && word.chars().all(is_shell_safe);
+} {
+    escape.len(home) => escape.pipe(home, buffer);
+    if is_safe {
+        escape.len(word)
+    } else {
+        2 * escape.len("'")
+            + word.split('\'').map(|part| escape.len(part)).sum::<usize>()
+            + word.matches('\'').count() * escape.len("'\\''")
+    } => if is_safe {
+        escape.pipe(word, buffer);
+    } else {
+        // Single quotes cannot be escaped inside single quotes: 'it'\''s'
+        escape.pipe("'", buffer);
+        let mut delim = "";
+        for part in word.split('\'') {
+            escape.pipe(delim, buffer);
+            escape.pipe(part, buffer);
+            delim = "'\\''";
+        }
+        escape.pipe("'", buffer);
+    };
+});
@@ -2,7 +2,7 @@ use crate::constants::{KEYCODES, MODIFIERS};
 use crate::parser::{shortcuts::ShortcutOwner, Shortcut};
 use crate::sidebyside_len_and_push;
 
-use super::{Consumer, DeserialiseChord, DeserialiseHotkey, PreallocLen, PreallocPush};
+use super::{command_fragments, DeserialiseChord, DeserialiseHotkey, PreallocLen, PreallocPush};
 
 //run: cargo test -- --nocapture
 
@@ -16,21 +16,11 @@ sidebyside_len_and_push!(shortcut_len, shortcut_pipe<U>(shortcut: &Shortcut, ext
     DeserialiseHotkey(" ; ", shortcut.hotkey).len(extra) => DeserialiseHotkey(" ; ", shortcut.hotkey).pipe(extra, buffer);
     1 => if shortcut.is_placeholder { buffer.consume("!") } else { buffer.consume("|") };
     " ";
-    shortcut.command.len() => shortcut.command.iter().for_each(|with_span| buffer.consume(with_span.source));
+    command_fragments(shortcut).map(str::len).sum::<usize>() => command_fragments(shortcut).for_each(|frag| buffer.consume(frag));
     "\n";
 });
 
-pub struct Wrapper();
-impl PreallocLen<&ShortcutOwner<'_>> for Wrapper {
-    fn len(&self, owner: &ShortcutOwner<'_>) -> usize {
-        len((), owner)
-    }
-}
-impl<U: Consumer> PreallocPush<&ShortcutOwner<'_>, U> for Wrapper {
-    fn pipe(&self, owner: &ShortcutOwner<'_>, buffer: &mut U) {
-        pipe((), owner, buffer)
-    }
-}
+wrapper!(len, pipe(|_| ()));
 sidebyside_len_and_push!(len, pipe<U>(_me: (), owner: &ShortcutOwner, buffer: U) {} {
     owner.to_iter().map(|s| shortcut_len(&s, DEBUG_CONSTANTS)).sum::<usize>() => {};
     "==== Placeholders ====\n";
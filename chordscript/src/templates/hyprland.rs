@@ -0,0 +1,130 @@
+use crate::constants::{Keycodes, Modifiers, KEYCODES, MODIFIERS};
+use crate::errors::ErrorKind;
+use crate::parser::keyspaces::{process, Action, Keyspace};
+use crate::parser::{shortcuts::ShortcutOwner, Chord};
+use crate::reporter::MarkupError;
+use crate::sidebyside_len_and_push;
+
+use super::i3_shell::{TITLE_CONSTANTS, TITLE_DELIM};
+use super::{
+    trim_command, verify_one_line, DeserialiseChord, DeserialiseHotkey, Escape, PreallocLen, PreallocPush,
+};
+
+//run: cargo test -- --nocapture
+
+// Only the modifiers are pushed by 'InnerChord::pipe()', the key is its own
+// comma-separated field
+const BIND_CONSTANTS: DeserialiseChord = DeserialiseChord {
+    delim: " ",
+    mod_to_str: &{
+        let mut base = MODIFIERS;
+        base[Modifiers::Alt.id()] = "ALT";
+        base[Modifiers::Ctrl.id()] = "CTRL";
+        base[Modifiers::Shift.id()] = "SHIFT";
+        base[Modifiers::Super.id()] = "SUPER";
+        base
+    },
+    key_to_str: &[],
+};
+
+// ',' separates the fields of a 'bind'
+const KEYS: [&str; KEYCODES.len()] = {
+    let mut base = KEYCODES;
+    base[Keycodes::Comma.id()] = "comma";
+    base[Keycodes::Period.id()] = "period";
+    base
+};
+
+// Hyprland reads '#' as the start of a comment unless it is doubled
+const ESCAPE: Escape = Escape(&[('#', "##")]);
+
+pub(super) fn verify(owner: &ShortcutOwner) -> Result<(), MarkupError> {
+    verify_one_line(owner, ErrorKind::HyprlandNewlineInCommand)
+}
+
+sidebyside_len_and_push!(bind_len, bind_pipe<U>(trigger: &Chord, dispatcher: &str, buffer: U) {
+    let chord = &trigger.chord;
+} {
+    "bind = ";
+    chord.len(BIND_CONSTANTS) => chord.pipe(BIND_CONSTANTS, buffer);
+    ", ";
+    KEYS.get(chord.key).map_or(0, |k| k.len()) => if let Some(key) = KEYS.get(chord.key) {
+        buffer.consume(key);
+    };
+    ", ";
+    dispatcher.len() => buffer.consume(dispatcher);
+    ", ";
+});
+
+// Hyprland runs every bind of a key, so a command in a submap is followed by
+// a second bind on the same key that leaves the submap
+sidebyside_len_and_push!(action_len, action_pipe<U>(me: &Action, in_submap: bool, buffer: U) {
+    let trigger = me.key_trigger();
+} {
+    bind_len(trigger, "submap") => match me {
+        Action::SetState(_) => bind_pipe(trigger, "submap", buffer),
+        Action::Command(_, _) => bind_pipe(trigger, "exec", buffer),
+    };
+    match me {
+        Action::SetState(title) => DeserialiseHotkey(TITLE_DELIM, title).len(TITLE_CONSTANTS),
+        Action::Command(_, shortcut) => trim_command(shortcut.command).map(|frag| ESCAPE.len(frag)).sum::<usize>(),
+    } => match me {
+        Action::SetState(title) => DeserialiseHotkey(TITLE_DELIM, title).pipe(TITLE_CONSTANTS, buffer),
+        Action::Command(_, shortcut) => trim_command(shortcut.command).for_each(|frag| ESCAPE.pipe(frag, buffer)),
+    };
+    "\n";
+    if in_submap { bind_len(trigger, "submap") + "reset\n".len() } else { 0 } => if let (true, Action::Command(_, _)) = (in_submap, me) {
+        bind_pipe(trigger, "submap", buffer);
+        buffer.consume("reset\n");
+    };
+});
+
+sidebyside_len_and_push!(keyspace_len, keyspace_pipe<U>(me: &Keyspace, _extra: (), buffer: U) {} {
+    "\nsubmap = ";
+    DeserialiseHotkey(TITLE_DELIM, me.title).len(TITLE_CONSTANTS) =>
+        DeserialiseHotkey(TITLE_DELIM, me.title).pipe(TITLE_CONSTANTS, buffer);
+    "\n";
+    me.actions.iter().map(|action| action_len(action, true)).sum::<usize>() =>
+        me.actions.iter().for_each(|action| action_pipe(action, true, buffer));
+    "bind = , escape, submap, reset\n";
+    "submap = reset\n";
+});
+
+wrapper!(len, pipe(|_| ()));
+sidebyside_len_and_push!(len, pipe<U>(_me: (), shortcut_owner: &ShortcutOwner, buffer: U) {
+    let owner = process(shortcut_owner);
+    let mut iter = owner.to_iter();
+    let modeless = iter.next().expect("DEV: We always expect `title: []` to exist");
+} {
+    modeless.actions.iter().map(|action| action_len(action, false)).sum::<usize>() =>
+        modeless.actions.iter().for_each(|action| action_pipe(action, false, buffer));
+    iter.map(|keyspace| keyspace_len(&keyspace, ())).sum::<usize>() =>
+        iter.for_each(|keyspace| keyspace_pipe(&keyspace, (), buffer));
+});
+
+#[test]
+fn submaps_and_escaping() {
+    use crate::parser::parse_to_shortcuts;
+
+    let owner = parse_to_shortcuts(
+        "|super a| echo \"#1\"\n\
+        |super space ; {{h, l}}| hyprctl dispatch workspace {{-1,+1}}\n\
+        |alt ,| echo\n",
+    )
+    .unwrap();
+    let format = crate::Format::from_str("hyprland", None).unwrap();
+    assert_eq!(
+        format.pipe_to_string(&owner).unwrap(),
+        "bind = SUPER, a, exec, echo \"##1\"\n\
+        bind = SUPER, space, submap, M+space\n\
+        bind = ALT, comma, exec, echo\n\
+        \n\
+        submap = M+space\n\
+        bind = , h, exec, hyprctl dispatch workspace -1\n\
+        bind = , h, submap, reset\n\
+        bind = , l, exec, hyprctl dispatch workspace +1\n\
+        bind = , l, submap, reset\n\
+        bind = , escape, submap, reset\n\
+        submap = reset\n"
+    );
+}
@@ -0,0 +1,48 @@
+use crate::errors::ErrorKind;
+use crate::parser::{shortcuts::ShortcutOwner, Shortcut};
+use crate::reporter::MarkupError;
+use crate::sidebyside_len_and_push;
+
+use super::i3_shell::{len, pipe, Exec, Flavour};
+use super::{trim_command, verify_one_line, Escape};
+
+//run: cargo test -- --nocapture
+
+// The command after 'bindsym' is parsed again by i3 when it runs, which only
+// unescapes '\"' and '\\' inside of a quoted string
+pub(super) const ESCAPE: Escape = Escape(&[('"', "\\\""), ('\\', "\\\\")]);
+
+// A line in the i3 config cannot contain a newline, and joining the lines
+// would change what the shellscript means
+pub(super) fn verify(owner: &ShortcutOwner) -> Result<(), MarkupError> {
+    verify_one_line(owner, ErrorKind::I3NewlineInCommand)
+}
+
+// The inside of 'exec --no-startup-id "..."'
+sidebyside_len_and_push!(pub(super) inline_len, inline_pipe<U>(shortcut: &Shortcut, _extra: (), buffer: U) {
+    let command = trim_command(shortcut.command);
+} {
+    command.clone().map(|frag| ESCAPE.len(frag)).sum::<usize>() =>
+        command.for_each(|frag| ESCAPE.pipe(frag, buffer));
+});
+
+wrapper!(len, pipe(|_| Flavour::i3(Exec::Inline)));
+
+#[test]
+fn commands_are_escaped() {
+    use crate::parser::parse_to_shortcuts;
+
+    let owner = parse_to_shortcuts("|super {{a, b}}| notify-send \"{{one,two}}\" 'it s' C:\\dir\n").unwrap();
+    let format = crate::Format::from_str("i3", None).unwrap();
+    assert_eq!(
+        format.pipe_to_string(&owner).unwrap(),
+        "bindsym Mod4+a exec --no-startup-id \"notify-send \\\"one\\\" 'it s' C:\\\\dir\"; mode \"default\";\n\
+        bindsym Mod4+b exec --no-startup-id \"notify-send \\\"two\\\" 'it s' C:\\\\dir\"; mode \"default\";\n\n"
+    );
+
+    let owner = parse_to_shortcuts("|super a|\n  echo 1\n  echo 2\n").unwrap();
+    match format.pipe_to_string(&owner) {
+        Err(crate::FormatError::Unsupported(err)) => assert_eq!(err.kind(), ErrorKind::I3NewlineInCommand),
+        other => panic!("{:?}", other),
+    }
+}
@@ -3,15 +3,14 @@ use crate::parser::keyspaces::{process, Action, Keyspace};
 use crate::parser::shortcuts::ShortcutOwner;
 use crate::sidebyside_len_and_push;
 
+use super::i3::{inline_len, inline_pipe, ESCAPE};
 use super::shellscript::{SHELL_CHORD_DELIM, SHELL_CONSTANTS};
-use super::{
-    Consumer, DeserialiseChord, DeserialiseHotkey, PreallocLen, PreallocPush, CHORD_MAX_PUSH_LEN,
-};
+use super::{shell_quote_len, shell_quote_pipe, Consumer, DeserialiseChord, DeserialiseHotkey, PreallocLen, PreallocPush};
 
 //run: cargo test -- --nocapture
 
-const TITLE_DELIM: &str = ";";
-const TITLE_CONSTANTS: DeserialiseChord = DeserialiseChord {
+pub(super) const TITLE_DELIM: &str = ";";
+pub(super) const TITLE_CONSTANTS: DeserialiseChord = DeserialiseChord {
     delim: "+",
     mod_to_str: &{
         let mut base = MODIFIERS;
@@ -40,97 +39,137 @@ const KEYBIND_CONSTANTS: DeserialiseChord = DeserialiseChord {
     key_to_str: &KEYCODES,
 };
 
-const UNUSED: DeserialiseChord = DeserialiseChord {
-    delim: "",
-    mod_to_str: &MODIFIERS,
-    key_to_str: &KEYCODES,
-};
+// The i3 config layout is the same whether we call the shell runner or
+// inline the commands (see 'i3.rs'), and sway shares it too (see 'sway.rs')
+#[derive(Clone, Copy)]
+pub(super) enum Exec<'a> {
+    Runner(&'a str), // The command that calls the shell runner
+    Inline,
+}
+
+#[derive(Clone, Copy)]
+pub(super) struct Flavour<'a> {
+    pub(super) exec: Exec<'a>,
+    pub(super) bindsym: &'static str,
+    pub(super) exec_prefix: &'static str,
+}
+
+impl<'a> Flavour<'a> {
+    pub(super) const fn i3(exec: Exec<'a>) -> Self {
+        Self {
+            exec,
+            bindsym: "bindsym ",
+            exec_prefix: " exec --no-startup-id \"",
+        }
+    }
+}
 
 //struct WrapAction<'a, 'b>(Action<'a, 'b>);
-impl<'a, 'b> PreallocLen<DeserialiseChord> for Action<'a, 'b> {
-    fn len(&self, extra: DeserialiseChord) -> usize {
+impl<'a, 'b> PreallocLen<Flavour<'_>> for Action<'a, 'b> {
+    fn len(&self, extra: Flavour) -> usize {
         action_len(self, extra)
     }
 }
-impl<'a, 'b, U: Consumer> PreallocPush<DeserialiseChord, U> for Action<'a, 'b> {
-    fn pipe(&self, extra: DeserialiseChord, buffer: &mut U) {
+impl<'a, 'b, U: Consumer> PreallocPush<Flavour<'_>, U> for Action<'a, 'b> {
+    fn pipe(&self, extra: Flavour, buffer: &mut U) {
         action_pipe(self, extra, buffer)
     }
 }
 
-sidebyside_len_and_push!(action_len, action_pipe<U>(me: &Action, _a: DeserialiseChord, buffer: U) {} {
-    "bindsym ";
-    CHORD_MAX_PUSH_LEN => me.key_trigger().chord.pipe(KEYBIND_CONSTANTS, buffer);
-    1 => match me {
+sidebyside_len_and_push!(action_len, action_pipe<U>(me: &Action, flavour: Flavour, buffer: U) {
+    let trigger = &me.key_trigger().chord;
+} {
+    flavour.bindsym.len() => buffer.consume(flavour.bindsym);
+    trigger.len(KEYBIND_CONSTANTS) => trigger.pipe(KEYBIND_CONSTANTS, buffer);
+    flavour.exec_prefix.len().max(" mode \"".len()) => match me {
         Action::SetState(_) => buffer.consume(" mode \""),
-        Action::Command(_, _) => buffer.consume(" bindsym exec --no-startup-id \""),
+        Action::Command(_, _) => buffer.consume(flavour.exec_prefix),
     };
 
-    match me {
-        Action::SetState(title) => DeserialiseHotkey(TITLE_DELIM, title).len(TITLE_CONSTANTS) + "\";\n".len(),
-        Action::Command(_, shortcut) => {
-            "shortcuts.sh".len()
+    match (me, flavour.exec) {
+        (Action::SetState(title), _) => DeserialiseHotkey(TITLE_DELIM, title).len(TITLE_CONSTANTS) + "\";\n".len(),
+        (Action::Command(_, shortcut), Exec::Runner(runner)) => {
+            shell_quote_len(runner, ESCAPE)
             + 1
             + DeserialiseHotkey(SHELL_CHORD_DELIM, shortcut.hotkey).len(SHELL_CONSTANTS)
             + 1
+            + "\"; mode \"default\";\n".len()
         }
-    } => match me {
-        Action::SetState(title) => {
+        (Action::Command(_, shortcut), Exec::Inline) => {
+            inline_len(shortcut, ()) + "\"; mode \"default\";\n".len()
+        }
+    } => match (me, flavour.exec) {
+        (Action::SetState(title), _) => {
             //debug_assert!()
             DeserialiseHotkey(TITLE_DELIM, title).pipe(TITLE_CONSTANTS, buffer);
             buffer.consume("\";\n");
         }
-        Action::Command(_trigger, shortcut) => {
-            buffer.consume("shortcuts.sh");
+        (Action::Command(_trigger, shortcut), Exec::Runner(runner)) => {
+            shell_quote_pipe(runner, ESCAPE, buffer);
             buffer.consume(" '");
             DeserialiseHotkey(SHELL_CHORD_DELIM, shortcut.hotkey).pipe(SHELL_CONSTANTS, buffer);
             buffer.consume("'\"; mode \"default\";\n");
         }
+        (Action::Command(_trigger, shortcut), Exec::Inline) => {
+            inline_pipe(shortcut, (), buffer);
+            buffer.consume("\"; mode \"default\";\n");
+        }
     };
 });
 
-impl<'a, 'b, 'c> PreallocLen<DeserialiseChord> for Keyspace<'a, 'b, 'c> {
-    fn len(&self, extra: DeserialiseChord) -> usize {
+impl<'a, 'b, 'c> PreallocLen<Flavour<'_>> for Keyspace<'a, 'b, 'c> {
+    fn len(&self, extra: Flavour) -> usize {
         keyspace_len(self, extra)
     }
 }
-impl<'a, 'b, 'c, U: Consumer> PreallocPush<DeserialiseChord, U> for Keyspace<'a, 'b, 'c> {
-    fn pipe(&self, extra: DeserialiseChord, buffer: &mut U) {
+impl<'a, 'b, 'c, U: Consumer> PreallocPush<Flavour<'_>, U> for Keyspace<'a, 'b, 'c> {
+    fn pipe(&self, extra: Flavour, buffer: &mut U) {
         keyspace_pipe(self, extra, buffer)
     }
 }
-sidebyside_len_and_push!(keyspace_len, keyspace_pipe<U>(me: &Keyspace, _a: DeserialiseChord, buffer: U) {} {
+sidebyside_len_and_push!(keyspace_len, keyspace_pipe<U>(me: &Keyspace, flavour: Flavour, buffer: U) {} {
     "\nmode \"";
         DeserialiseHotkey(TITLE_DELIM, me.title).len(TITLE_CONSTANTS) =>
             DeserialiseHotkey(TITLE_DELIM, me.title).pipe(TITLE_CONSTANTS, buffer);
         "\" {\n";
-        me.actions.iter().map(|a| a.len(UNUSED) + 1).sum::<usize>() => for action in me.actions {
+        me.actions.iter().map(|a| a.len(flavour) + 2).sum::<usize>() => for action in me.actions {
             buffer.consume("  ");
-            action.pipe(UNUSED, buffer);
+            action.pipe(flavour, buffer);
         };
-        "  bindsym Escape mode \"default\";\n";
+        "  ";
+        flavour.bindsym.len() => buffer.consume(flavour.bindsym);
+        "Escape mode \"default\";\n";
     "}\n";
 });
 
-pub struct Wrapper();
-impl PreallocLen<&ShortcutOwner<'_>> for Wrapper {
-    fn len(&self, owner: &ShortcutOwner<'_>) -> usize {
-        len((), owner)
-    }
-}
-impl<U: Consumer> PreallocPush<&ShortcutOwner<'_>, U> for Wrapper {
-    fn pipe(&self, owner: &ShortcutOwner<'_>, buffer: &mut U) {
-        pipe((), owner, buffer)
-    }
-}
-sidebyside_len_and_push!(len, pipe<U>(_me: (), shortcut_owner: &ShortcutOwner, buffer: U) {
+wrapper!(len, pipe(|format| Flavour::i3(Exec::Runner(format.runner))));
+sidebyside_len_and_push!(pub(super) len, pipe<U>(flavour: Flavour, shortcut_owner: &ShortcutOwner, buffer: U) {
     let owner = process(shortcut_owner);
     let mut iter = owner.to_iter();
     let modeless = iter.next().expect("DEV: We always expect `title: []` to exist");
 } {
-    modeless.actions.iter().map(|action| action.len(UNUSED)).sum::<usize>() =>
-        modeless.actions.iter().for_each(|action| action.pipe(UNUSED, buffer));
+    modeless.actions.iter().map(|action| action.len(flavour)).sum::<usize>() =>
+        modeless.actions.iter().for_each(|action| action.pipe(flavour, buffer));
     "\n";
-    iter.map(|keyspace| keyspace.len(UNUSED)).sum::<usize>() =>
-        iter.for_each(|keyspace| keyspace.pipe(UNUSED, buffer));
+    iter.map(|keyspace| keyspace.len(flavour)).sum::<usize>() =>
+        iter.for_each(|keyspace| keyspace.pipe(flavour, buffer));
 });
+
+#[test]
+fn runner_is_quoted() {
+    use crate::parser::parse_to_shortcuts;
+
+    let owner = parse_to_shortcuts("|super a| echo\n").unwrap();
+    let format = crate::Format::from_str("i3", Some("~/my bin/it's.sh")).unwrap();
+    let mut buffer = String::with_capacity(Wrapper().len((&owner, &format)));
+    Wrapper().pipe((&owner, &format), &mut buffer);
+    assert_eq!(
+        buffer,
+        "bindsym Mod4+a exec --no-startup-id \"~/'my bin/it'\\\\''s.sh' 'super a'\"; mode \"default\";\n\n"
+    );
+
+    let format = crate::Format::from_str("i3", Some("~/bin/run.sh")).unwrap();
+    let mut buffer = String::with_capacity(Wrapper().len((&owner, &format)));
+    Wrapper().pipe((&owner, &format), &mut buffer);
+    assert!(buffer.contains("exec --no-startup-id \"~/bin/run.sh 'super a'\";"), "{}", buffer);
+}
@@ -0,0 +1,122 @@
+use crate::constants::{Keycodes, Modifiers, KEYCODES, MODIFIERS};
+use crate::errors::ErrorKind;
+use crate::parser::{hotkey_span, shortcuts::ShortcutOwner, Shortcut};
+use crate::reporter::MarkupError;
+use crate::sidebyside_len_and_push;
+
+use super::{trim_command, DeserialiseChord, Escape};
+
+//run: cargo test -- --nocapture
+
+// LeftWM 'keybind' entries for 'config.toml'. Only the modifiers are pushed
+// by 'InnerChord::pipe()', so the quotes are part of the names.
+const MODIFIER_CONSTANTS: DeserialiseChord = DeserialiseChord {
+    delim: ", ",
+    mod_to_str: &{
+        let mut base = MODIFIERS;
+        base[Modifiers::Alt.id()] = "\"Mod1\"";
+        base[Modifiers::Ctrl.id()] = "\"Control\"";
+        base[Modifiers::Shift.id()] = "\"Shift\"";
+        base[Modifiers::Super.id()] = "\"modkey\"";
+        base
+    },
+    key_to_str: &[],
+};
+
+// LeftWM uses the X11 keysym names with the 'XK_' prefix stripped
+const KEYS: [&str; KEYCODES.len()] = {
+    let mut base = KEYCODES;
+    base[Keycodes::Comma.id()] = "comma";
+    base[Keycodes::Period.id()] = "period";
+    base[Keycodes::XF86MonBrightnessUp.id()] = "XF86XK_MonBrightnessUp";
+    base[Keycodes::XF86MonBrightnessDown.id()] = "XF86XK_MonBrightnessDown";
+    base
+};
+
+// TOML basic string
+const ESCAPE: Escape = Escape(&[
+    ('"', "\\\""),
+    ('\\', "\\\\"),
+    ('\n', "\\n"),
+    ('\t', "\\t"),
+    ('\r', "\\r"),
+]);
+
+// LeftWM has no modes, so there is nowhere to put the rest of a chord chain
+pub(super) fn verify(owner: &ShortcutOwner) -> Result<(), MarkupError> {
+    match owner
+        .to_iter()
+        .find(|s| !s.is_placeholder && s.hotkey.len() > 1)
+    {
+        Some(shortcut) => {
+            let context = shortcut.hotkey[0].context;
+            Err(MarkupError::from_str(
+                context,
+                hotkey_span(context, shortcut.hotkey),
+                ErrorKind::LeftWmMultiChord,
+            ))
+        }
+        None => Ok(()),
+    }
+}
+
+sidebyside_len_and_push!(shortcut_len, shortcut_pipe<U>(shortcut: &Shortcut, _extra: (), buffer: U) {
+    // 'verify()' guarantees one chord
+    let chord = &shortcut.hotkey[0].chord;
+    let command = trim_command(shortcut.command);
+} {
+    "[[keybind]]\n";
+    "command = \"Execute\"\n";
+    "value = \"";
+    command.clone().map(|frag| ESCAPE.len(frag)).sum::<usize>() =>
+        command.for_each(|frag| ESCAPE.pipe(frag, buffer));
+    "\"\n";
+    "modifier = [";
+    chord.len(MODIFIER_CONSTANTS) => chord.pipe(MODIFIER_CONSTANTS, buffer);
+    "]\n";
+    "key = \"";
+    KEYS.get(chord.key).map_or(0, |k| k.len()) => if let Some(key) = KEYS.get(chord.key) {
+        buffer.consume(key);
+    };
+    "\"\n";
+});
+
+wrapper!(len, pipe(|_| ()));
+sidebyside_len_and_push!(len, pipe<U>(_me: (), owner: &ShortcutOwner, buffer: U) {} {
+    owner.to_iter().map(|s| "\n".len() + shortcut_len(&s, ())).sum::<usize>() => {
+        let mut delim = "";
+        owner.to_iter().filter(|s| !s.is_placeholder).for_each(|s| {
+            buffer.consume(delim);
+            shortcut_pipe(&s, (), buffer);
+            delim = "\n";
+        });
+    };
+});
+
+#[test]
+fn keybinds() {
+    use crate::parser::parse_to_shortcuts;
+
+    let owner = parse_to_shortcuts("|super a| echo \"#1\"\n|super shift ,| echo\n!super q ; b! placeholder\n").unwrap();
+    let format = crate::Format::from_str("leftwm", None).unwrap();
+    assert_eq!(
+        format.pipe_to_string(&owner).unwrap(),
+        "[[keybind]]\n\
+        command = \"Execute\"\n\
+        value = \"echo \\\"#1\\\"\"\n\
+        modifier = [\"modkey\"]\n\
+        key = \"a\"\n\
+        \n\
+        [[keybind]]\n\
+        command = \"Execute\"\n\
+        value = \"echo\"\n\
+        modifier = [\"Shift\", \"modkey\"]\n\
+        key = \"comma\"\n"
+    );
+
+    let owner = parse_to_shortcuts("|super a ; b| echo\n").unwrap();
+    match format.pipe_to_string(&owner) {
+        Err(crate::FormatError::Unsupported(err)) => assert_eq!(err.kind(), ErrorKind::LeftWmMultiChord),
+        other => panic!("{:?}", other),
+    }
+}
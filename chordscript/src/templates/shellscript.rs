@@ -2,11 +2,11 @@ use crate::constants::{KEYCODES, MODIFIERS};
 use crate::parser::{shortcuts::ShortcutOwner, Shortcut};
 use crate::sidebyside_len_and_push;
 
-use super::{Consumer, DeserialiseChord, DeserialiseHotkey, PreallocLen, PreallocPush};
+use super::{command_fragments, DeserialiseChord, DeserialiseHotkey, PreallocLen, PreallocPush};
 
 //run: cargo test -- --nocapture
 
-//// @TODO: Extract chord into WithSpan<Chord>, would remove one lifetime
+// @TODO: Extract chord into WithSpan<Chord>, would remove one lifetime
 //struct DeserialisedChord {
 //    delim: &'static str,
 //    key: usize,
@@ -29,34 +29,128 @@ sidebyside_len_and_push!(shortcut_len, shortcut_pipe<U>
     " '";
     wrap_hotkey.len(extra) => wrap_hotkey.pipe(extra, buffer);
     "')  ";
-    shortcut.command.len() => for with_span in shortcut.command {
-        buffer.consume(with_span.source);
-    };
+    command_fragments(&shortcut).map(str::len).sum::<usize>() =>
+        command_fragments(&shortcut).for_each(|frag| buffer.consume(frag));
     "\n";
 });
 
-pub struct Wrapper();
-impl PreallocLen<&ShortcutOwner<'_>> for Wrapper {
-    fn len(&self, owner: &ShortcutOwner<'_>) -> usize {
-        len((), owner)
-    }
+// What the runner does for a hotkey that it does not know
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fallback<'a> {
+    NotifySend,
+    Logger,
+    Stderr,
+    Command(&'a str), // Given the message as the last argument
+    Nothing,
 }
-impl<U: Consumer> PreallocPush<&ShortcutOwner<'_>, U> for Wrapper {
-    fn pipe(&self, owner: &ShortcutOwner<'_>, buffer: &mut U) {
-        pipe((), owner, buffer)
+
+impl<'a> Fallback<'a> {
+    // Anything that is not one of the names is a custom command
+    pub fn from_name(fallback: &'a str) -> Self {
+        match fallback {
+            "notify-send" => Self::NotifySend,
+            "logger" => Self::Logger,
+            "stderr" => Self::Stderr,
+            "none" => Self::Nothing,
+            command => Self::Command(command),
+        }
+    }
+
+    fn command(&self) -> (&'a str, &'static str) {
+        match self {
+            Self::NotifySend => ("notify-send chordscript", ""),
+            Self::Logger => ("logger -t chordscript", ""),
+            Self::Stderr => ("printf %s\\\\n", " >&2"),
+            Self::Command(command) => (command, ""),
+            Self::Nothing => ("", ""),
+        }
     }
 }
-sidebyside_len_and_push!(len, pipe<U>(_a: (), owner: &ShortcutOwner, buffer: U) {} {
+
+sidebyside_len_and_push!(fallback_len, fallback_pipe<U>(fallback: Fallback, _extra: (), buffer: U) {
+    let (command, redirect) = fallback.command();
+} {
+    ";; *)  ";
+    command.len() + " \"invalid key combination ${1}\"".len() + redirect.len() + "; ".len()
+    => if fallback != Fallback::Nothing {
+        buffer.consume(command);
+        buffer.consume(" \"invalid key combination ${1}\"");
+        buffer.consume(redirect);
+        buffer.consume("; ");
+    };
+    "exit 1\n";
+});
+
+wrapper!(len, pipe(|format| format.fallback));
+sidebyside_len_and_push!(len, pipe<U>(fallback: Fallback, owner: &ShortcutOwner, buffer: U) {
+    let shortcuts = || owner.to_iter().filter(|s| !s.is_placeholder);
+} {
     "#!/bin/sh\n";
     "case \"${1}\"\n";
-    owner.to_iter().map(|s| 1 + shortcut_len(s, SHELL_CONSTANTS)).sum::<usize>() => {
-        let mut prefix = "in";
-        owner.to_iter().filter(|s| !s.is_placeholder).for_each(|s| {
-            buffer.consume(prefix);
+    // Hotkeys are only key names, so they never need escaping in single quotes
+    "in --list|--help)  printf %s\\\\n";
+    shortcuts().map(|s| " ''".len() + DeserialiseHotkey(SHELL_CHORD_DELIM, s.hotkey).len(SHELL_CONSTANTS)).sum::<usize>()
+    => shortcuts().for_each(|s| {
+        buffer.consume(" '");
+        DeserialiseHotkey(SHELL_CHORD_DELIM, s.hotkey).pipe(SHELL_CONSTANTS, buffer);
+        buffer.consume("'");
+    });
+    "\n";
+    shortcuts().map(|s| ";;".len() + shortcut_len(s, SHELL_CONSTANTS)).sum::<usize>() =>
+        shortcuts().for_each(|s| {
+            buffer.consume(";;");
             shortcut_pipe(s, SHELL_CONSTANTS, buffer);
-            prefix = ";;";
         });
-    };
-    "*)  notify.sh \"invalid key combination ${1}\"; exit 1\n";
+    fallback_len(fallback, ()) => fallback_pipe(fallback, (), buffer);
     "esac\n";
 });
+
+#[test]
+fn list_and_fallback() {
+    use crate::parser::parse_to_shortcuts;
+
+    let owner = parse_to_shortcuts("|super a| echo a\n|super b ; c| echo b\n!super q! reserved\n").unwrap();
+    let mut format = crate::Format::from_str("shell", None).unwrap();
+    let mut buffer = String::with_capacity(Wrapper().len((&owner, &format)));
+    Wrapper().pipe((&owner, &format), &mut buffer);
+    assert_eq!(
+        buffer,
+        "#!/bin/sh\n\
+        case \"${1}\"\n\
+        in --list|--help)  printf %s\\\\n 'super a' 'super b ; c'\n\
+        ;; 'super a')   echo a\n\
+        ;; 'super b ; c')   echo b\n\
+        ;; *)  printf %s\\\\n \"invalid key combination ${1}\" >&2; exit 1\n\
+        esac\n"
+    );
+
+    format.fallback = Fallback::from_name("none");
+    let mut buffer = String::with_capacity(Wrapper().len((&owner, &format)));
+    Wrapper().pipe((&owner, &format), &mut buffer);
+    assert!(buffer.contains("\n;; *)  exit 1\nesac\n"), "{}", buffer);
+}
+
+#[test]
+fn trim_markers() {
+    use crate::parser::parse_to_shortcuts;
+
+    let owner = parse_to_shortcuts(
+        "|super a| echo kept  \n\
+        |super b|- echo stripped  \n\n\
+        |super {{c, e}}|<\n\
+        \x20   if [ -n \"${1}\" ]; then\n\
+        \x20     {{echo, printf %s}} \"${1}\"\n\
+        \n\
+        \x20   fi\n\
+        |super d|<  a\n\x20   b\n\x20     c\n",
+    )
+    .unwrap();
+    let format = crate::Format::from_str("shell", None).unwrap();
+    let shell = format.pipe_to_string(&owner).unwrap();
+    assert!(shell.contains(";; 'super a')   echo kept  \n"), "{}", shell);
+    assert!(shell.contains(";; 'super b')  echo stripped\n"), "{}", shell);
+    assert!(shell.contains(";; 'super c')  if [ -n \"${1}\" ]; then\n  echo \"${1}\"\n\nfi\n"), "{}", shell);
+    // The space after ',' is part of the choice
+    assert!(shell.contains(";; 'super e')  if [ -n \"${1}\" ]; then\n   printf %s \"${1}\"\n"), "{}", shell);
+    assert!(shell.contains(";; 'super d')  a\nb\n  c\n;; *)"), "{}", shell);
+}
@@ -0,0 +1,39 @@
+use super::i3_shell::{len, pipe, Exec, Flavour};
+
+//run: cargo test -- --nocapture
+
+// sway reads the i3 config syntax, so this only swaps out the bits that
+// differ. '--to-code' binds by the keysym of the first configured layout,
+// and sway has no startup notifications to opt out of.
+const fn sway(exec: Exec) -> Flavour {
+    Flavour {
+        exec,
+        bindsym: "bindsym --to-code ",
+        exec_prefix: " exec \"",
+    }
+}
+
+// Commands are inlined into the config (escaping is the same as i3)
+wrapper!(len, pipe(|_| sway(Exec::Inline)));
+
+// Commands go through the shell runner (see 'shellscript.rs')
+wrapper!(ShellWrapper: len, pipe(|format| sway(Exec::Runner(format.runner))));
+
+#[test]
+fn to_code_without_startup_id() {
+    use crate::parser::parse_to_shortcuts;
+
+    let owner = parse_to_shortcuts("|super {{a, b}}| notify-send \"{{one,two}}\"\n|super c ; d| echo\n").unwrap();
+    let format = crate::Format::from_str("sway", None).unwrap();
+    let config = format.pipe_to_string(&owner).unwrap();
+    assert!(
+        config.starts_with(
+            "bindsym --to-code Mod4+a exec \"notify-send \\\"one\\\"\"; mode \"default\";\n\
+            bindsym --to-code Mod4+b exec \"notify-send \\\"two\\\"\"; mode \"default\";\n"
+        ),
+        "{}",
+        config
+    );
+    assert!(config.contains("bindsym --to-code d exec \"echo\"; mode \"default\";\n"), "{}", config);
+    assert!(!config.contains("--no-startup-id"), "{}", config);
+}
@@ -0,0 +1,310 @@
+use crate::constants::{Keycodes, KEYCODES, MODIFIERS};
+use crate::errors::ErrorKind;
+use crate::parser::{shortcuts::ShortcutOwner, Chord, Cursor, InnerChord, Shortcut, WithSpan};
+use crate::reporter::MarkupError;
+use crate::sidebyside_len_and_push;
+
+use super::{
+    stripped_range, trim_command, verify_commands, DeserialiseChord, DeserialiseHotkey,
+    PreallocLen,
+};
+
+//run: cargo test -- --nocapture
+
+// sxhkd uses the keysym names, which are mostly our names too. The modifiers
+// are the same. ',' would also split a '{..}' group.
+const SXHKD_CONSTANTS: DeserialiseChord = DeserialiseChord {
+    delim: " + ",
+    mod_to_str: &MODIFIERS,
+    key_to_str: &{
+        let mut base = KEYCODES;
+        base[Keycodes::Comma.id()] = "comma";
+        base[Keycodes::Period.id()] = "period";
+        base
+    },
+};
+const SXHKD_CHORD_DELIM: &str = " ; ";
+
+// Once a hotkey has a '{..}' sequence, sxhkd also expands braces in the
+// command. We do not attempt escaping, we just do not fold these.
+const SEQUENCE_SYNTAX: [char; 3] = ['{', '}', '\\'];
+
+// sxhkd joins lines that end with a backslash into one line, which would
+// change what a multi-line shellscript means
+pub(super) fn verify(owner: &ShortcutOwner) -> Result<(), MarkupError> {
+    verify_commands(owner, ErrorKind::SxhkdNewlineInCommand, |s| {
+        unwrap_choices(s.command).iter().all(|[_, core, _]| !core.contains('\n'))
+    })
+}
+
+// A fragment of a command with the spaces that replace its layout
+type Piece<'filestr> = [&'filestr str; 3];
+
+// Whitespace with a newline at the edge of a '{{..}}' choice only lays out
+// the choices, e.g. 'bspc {{\n  node,\n  desktop\n}}', so it becomes one
+// space. Any other newline stays, for 'verify()' to reject.
+fn unwrap_choices<'filestr>(command: &[WithSpan<'filestr, bool>]) -> Vec<Piece<'filestr>> {
+    let is_choice = command[stripped_range(command, true)].iter().map(|s| s.data);
+    let frags = trim_command(command).zip(is_choice).collect::<Vec<_>>();
+    let mut pieces = Vec::with_capacity(frags.len());
+    let mut is_after_space = true; // Nothing to separate at the start
+    for (i, &(frag, is_choice)) in frags.iter().enumerate() {
+        let mut piece = ["", frag, ""];
+        if is_choice {
+            let core = frag.trim_start();
+            if frag[..frag.len() - core.len()].contains('\n') {
+                piece[0] = if is_after_space { "" } else { " " };
+                piece[1] = core;
+            }
+            let core = piece[1].trim_end();
+            if piece[1][core.len()..].contains('\n') {
+                let next = frags.get(i + 1).map(|(f, _)| *f);
+                let is_before_space = next.is_none_or(|f| f.starts_with(char::is_whitespace));
+                piece[1] = core;
+                piece[2] = if is_before_space { "" } else { " " };
+            }
+        }
+        if let Some(c) = piece.iter().rev().find_map(|p| p.chars().next_back()) {
+            is_after_space = c.is_whitespace();
+        }
+        pieces.push(piece);
+    }
+    pieces
+}
+
+fn piece_len(piece: &Piece) -> usize {
+    piece.iter().map(|p| p.len()).sum()
+}
+
+fn is_sequence_syntax(piece: &Piece, c: &[char]) -> bool {
+    piece.iter().any(|p| p.contains(c))
+}
+
+// A shortcut with its command as sxhkd gets it
+struct Entry<'owner, 'filestr> {
+    shortcut: Shortcut<'owner, 'filestr>,
+    command: Vec<Piece<'filestr>>,
+}
+
+// A run of consecutive shortcuts that only differ by the key of the chord at
+// the 'fold' column, i.e. 'super + {a,b}'. A shortcut that cannot be folded
+// is a run of one.
+struct Block<'a, 'owner, 'filestr> {
+    entries: &'a [Entry<'owner, 'filestr>],
+    fold: Option<usize>,
+}
+
+fn into_blocks<'a, 'owner, 'filestr>(
+    entries: &'a [Entry<'owner, 'filestr>],
+) -> Vec<Block<'a, 'owner, 'filestr>> {
+    let mut blocks = Vec::with_capacity(entries.len());
+    let mut cursor = Cursor(0);
+    while let Some(first) = entries.get(cursor.0) {
+        let rest = &entries[cursor.0 + 1..];
+        let fold = rest.first().and_then(|next| fold_column(first, next));
+        let close = match fold {
+            Some(col) => rest
+                .iter()
+                .position(|s| fold_column(first, s) != Some(col))
+                .unwrap_or(rest.len()),
+            None => 0,
+        };
+        blocks.push(Block {
+            entries: &entries[cursor.move_to(cursor.0 + 1 + close)],
+            fold,
+        });
+    }
+    blocks
+}
+
+// The chord column that 'a' and 'b' can be folded along, if any
+fn fold_column(a: &Entry, b: &Entry) -> Option<usize> {
+    let (a_hotkey, b_hotkey) = (a.shortcut.hotkey, b.shortcut.hotkey);
+    if a_hotkey.len() != b_hotkey.len() {
+        return None;
+    }
+    let mut differs = a_hotkey
+        .iter()
+        .zip(b_hotkey)
+        .enumerate()
+        .filter(|(_, (x, y))| x != y);
+    let (col, (x, y)) = differs.next()?;
+    if differs.next().is_some() || x.chord.modifiers != y.chord.modifiers {
+        return None;
+    }
+
+    let is_foldable = a.command.len() == b.command.len()
+        && a.command.iter().zip(&b.command).all(|(p, q)| {
+            !is_sequence_syntax(p, &SEQUENCE_SYNTAX)
+                && !is_sequence_syntax(q, &SEQUENCE_SYNTAX)
+                && (p == q || !is_sequence_syntax(p, &[',']) && !is_sequence_syntax(q, &[',']))
+        });
+    is_foldable.then_some(col)
+}
+
+impl<'filestr> Block<'_, '_, 'filestr> {
+    // The 'nth' fragment of every shortcut's command
+    fn column(&self, nth: usize) -> impl Iterator<Item = &Piece<'filestr>> + Clone + '_ {
+        self.entries
+            .iter()
+            .map(move |e| e.command.get(nth).unwrap_or(&["", "", ""]))
+    }
+
+    fn keys(&self, col: usize) -> impl Iterator<Item = &'static str> + '_ {
+        self.entries
+            .iter()
+            .map(move |e| SXHKD_CONSTANTS.key_to_str[e.shortcut.hotkey[col].chord.key])
+    }
+    sidebyside_len_and_push!(fold_len, fold_pipe<U>(self: &Self, chord: &Chord, buffer: U) {
+        let col = self.fold.unwrap();
+        let modifiers_only = InnerChord {
+            key: KEYCODES.len(),
+            modifiers: chord.chord.modifiers,
+        };
+    } {
+        modifiers_only.len(SXHKD_CONSTANTS) => modifiers_only.pipe(SXHKD_CONSTANTS, buffer);
+        SXHKD_CONSTANTS.delim.len() => if modifiers_only.modifiers != 0 {
+            buffer.consume(SXHKD_CONSTANTS.delim);
+        };
+        "{";
+        self.keys(col).map(|k| k.len() + ",".len()).sum::<usize>() => {
+            let mut delim = "";
+            self.keys(col).for_each(|key| {
+                buffer.consume(delim);
+                buffer.consume(key);
+                delim = ",";
+            });
+        };
+        "}";
+    });
+
+    sidebyside_len_and_push!(hotkey_len, hotkey_pipe<U>(self: &Self, _extra: (), buffer: U) {
+        let hotkey = self.entries[0].shortcut.hotkey;
+    } {
+        DeserialiseHotkey(SXHKD_CHORD_DELIM, hotkey).len(SXHKD_CONSTANTS) => {};
+        self.fold.map(|col| self.fold_len(&hotkey[col])).unwrap_or(0) => {
+            let mut delim = "";
+            for (i, chord) in hotkey.iter().enumerate() {
+                buffer.consume(delim);
+                if self.fold == Some(i) {
+                    self.fold_pipe(chord, buffer);
+                } else {
+                    chord.chord.pipe(SXHKD_CONSTANTS, buffer);
+                }
+                delim = SXHKD_CHORD_DELIM;
+            }
+        };
+    });
+
+    sidebyside_len_and_push!(command_len, command_pipe<U>(self: &Self, _extra: (), buffer: U) {
+        let first = &self.entries[0].command;
+    } {
+        match self.fold {
+            Some(_) => first.iter().enumerate().map(|(i, piece)| {
+                if self.column(i).all(|member| member == piece) {
+                    piece_len(piece)
+                } else {
+                    "{}".len() + self.column(i).map(|m| piece_len(m) + ",".len()).sum::<usize>()
+                }
+            }).sum::<usize>(),
+            None => first.iter().map(piece_len).sum::<usize>(),
+        } => match self.fold {
+            Some(_) => first.iter().enumerate().for_each(|(i, piece)| {
+                if self.column(i).all(|member| member == piece) {
+                    piece.iter().for_each(|p| buffer.consume(p));
+                } else {
+                    let mut delim = "{";
+                    self.column(i).for_each(|member| {
+                        buffer.consume(delim);
+                        member.iter().for_each(|p| buffer.consume(p));
+                        delim = ",";
+                    });
+                    buffer.consume("}");
+                }
+            }),
+            None => first.iter().flatten().for_each(|p| buffer.consume(p)),
+        };
+    });
+}
+
+sidebyside_len_and_push!(block_len, block_pipe<U>(me: &Block, _extra: (), buffer: U) {} {
+    me.hotkey_len(()) => me.hotkey_pipe((), buffer);
+    "\n\t";
+    me.command_len(()) => me.command_pipe((), buffer);
+    "\n";
+});
+
+wrapper!(len, pipe(|_| ()));
+sidebyside_len_and_push!(len, pipe<U>(_me: (), owner: &ShortcutOwner, buffer: U) {
+    let entries = owner
+        .to_iter()
+        .filter(|s| !s.is_placeholder)
+        .map(|shortcut| Entry { command: unwrap_choices(shortcut.command), shortcut })
+        .collect::<Vec<_>>();
+    let blocks = into_blocks(&entries);
+} {
+    blocks.iter().map(|block| "\n".len() + block_len(block, ())).sum::<usize>() => {
+        let mut delim = "";
+        blocks.iter().for_each(|block| {
+            buffer.consume(delim);
+            block_pipe(block, (), buffer);
+            delim = "\n";
+        });
+    };
+});
+
+#[test]
+fn folds_permutations() {
+    use crate::parser::parse_to_shortcuts;
+
+    let owner = parse_to_shortcuts(
+        "|super {{h, j}}| bspc node -f {{west, south}}\n\
+        |super space ; super {{a, m}}|\n  $TERMINAL -e {{alsamixer,neomutt}}\n\
+        |super {{c, x}}| echo ${1}\n\
+        |alt Return| notify-send '{a,b}'\n\
+        !super q! placeholder\n",
+    )
+    .unwrap();
+
+    let format = crate::Format::from_str("sxhkd", None).unwrap();
+    let buffer = format.pipe_to_string(&owner).unwrap();
+    assert_eq!(
+        buffer,
+        "super + {h,j}\n\tbspc node -f {west, south}\n\
+        \n\
+        super + space ; super + {a,m}\n\t$TERMINAL -e {alsamixer,neomutt}\n\
+        \n\
+        super + c\n\techo ${1}\n\
+        \n\
+        super + x\n\techo ${1}\n\
+        \n\
+        alt + Return\n\tnotify-send '{a,b}'\n"
+    );
+
+    let owner = parse_to_shortcuts("|super ,| echo 1\n|super .| echo 2\n").unwrap();
+    let buffer = format.pipe_to_string(&owner).unwrap();
+    assert_eq!(buffer, "super + {comma,period}\n\t{echo 1,echo 2}\n");
+
+    // Only the layout of the choices has newlines
+    let owner = parse_to_shortcuts(
+        "|super {{1,2}}| fcitx-remote -s {{\n  us,\n  mozc\n}}\n\
+        |super 3| bspc node -f {{\n  west\n}}-1\n",
+    )
+    .unwrap();
+    let buffer = format.pipe_to_string(&owner).unwrap();
+    assert_eq!(
+        buffer,
+        "super + {1,2}\n\tfcitx-remote -s {us,mozc}\n\
+        \n\
+        super + 3\n\tbspc node -f west -1\n"
+    );
+
+    for source in ["|super a| if x; then\n  echo\nfi\n", "|super {{a,b}}| echo\n{{a,b}}\n"] {
+        let err = format.pipe_to_string(&parse_to_shortcuts(source).unwrap());
+        assert!(
+            matches!(err, Err(crate::FormatError::Unsupported(ref e)) if e.kind() == ErrorKind::SxhkdNewlineInCommand),
+            "{:?}",
+            err
+        );
+    }
+}
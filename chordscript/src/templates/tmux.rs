@@ -0,0 +1,152 @@
+use crate::constants::{Keycodes, Modifiers, KEYCODES, MODIFIERS};
+use crate::errors::ErrorKind;
+use crate::parser::keyspaces::{process, Action, Keyspace};
+use crate::parser::{shortcuts::ShortcutOwner, Hotkey};
+use crate::reporter::MarkupError;
+use crate::sidebyside_len_and_push;
+
+use super::{
+    trim_command, verify_one_line, DeserialiseChord, DeserialiseHotkey, Escape, PreallocLen, PreallocPush,
+};
+
+//run: cargo test -- --nocapture
+
+// tmux key names, an empty string is a key that tmux cannot bind
+const KEYS: [&str; KEYCODES.len()] = {
+    let mut base = KEYCODES;
+    base[Keycodes::Space.id()] = "Space";
+    base[Keycodes::Insert.id()] = "IC";
+    base[Keycodes::Return.id()] = "Enter";
+    base[Keycodes::BackSpace.id()] = "BSpace";
+    base[Keycodes::Semicolon.id()] = "\\;";
+    base[Keycodes::Print.id()] = "";
+    base[Keycodes::XF86MonBrightnessUp.id()] = "";
+    base[Keycodes::XF86MonBrightnessDown.id()] = "";
+    base
+};
+
+// e.g. 'ctrl alt a' -> 'M-C-a', 'verify()' rejects super
+const TMUX_CONSTANTS: DeserialiseChord = DeserialiseChord {
+    delim: "-",
+    mod_to_str: &{
+        let mut base = MODIFIERS;
+        base[Modifiers::Alt.id()] = "M";
+        base[Modifiers::Ctrl.id()] = "C";
+        base[Modifiers::Shift.id()] = "S";
+        base[Modifiers::Super.id()] = "";
+        base
+    },
+    key_to_str: &KEYS,
+};
+// Table names are only for us, so stick to names that need no escaping
+const TABLE_CONSTANTS: DeserialiseChord = DeserialiseChord {
+    key_to_str: &KEYCODES,
+    ..TMUX_CONSTANTS
+};
+const TABLE_DELIM: &str = " ";
+const ROOT_TABLE: &str = "root";
+
+// Inside of a double-quoted 'run-shell' command. tmux expands '$' and
+// formats ('#{..}') before handing it to the shell.
+const ESCAPE: Escape = Escape(&[('"', "\\\""), ('\\', "\\\\"), ('$', "\\$"), ('#', "##")]);
+
+pub(super) fn verify(owner: &ShortcutOwner) -> Result<(), MarkupError> {
+    for shortcut in owner.to_iter().filter(|s| !s.is_placeholder) {
+        for chord in shortcut.hotkey {
+            let (span, kind) = if chord.chord.modifiers & (1 << Modifiers::Super.id()) != 0 {
+                (chord.sources[Modifiers::Super.id() + 1], ErrorKind::TmuxSuper)
+            } else if KEYS.get(chord.chord.key).is_some_and(|k| k.is_empty()) {
+                (chord.sources[0], ErrorKind::TmuxUnknownKey)
+            } else {
+                continue;
+            };
+            return Err(MarkupError::from_str(chord.context, span, kind));
+        }
+    }
+    verify_one_line(owner, ErrorKind::TmuxNewlineInCommand)
+}
+
+// The root table is always active, every other table lasts for one key press
+sidebyside_len_and_push!(table_len, table_pipe<U>(title: Hotkey, _extra: (), buffer: U) {} {
+    ROOT_TABLE.len() + "\"\"".len() + DeserialiseHotkey(TABLE_DELIM, title).len(TABLE_CONSTANTS) =>
+        if title.is_empty() {
+            buffer.consume(ROOT_TABLE);
+        } else {
+            buffer.consume("\"");
+            DeserialiseHotkey(TABLE_DELIM, title).pipe(TABLE_CONSTANTS, buffer);
+            buffer.consume("\"");
+        };
+});
+
+sidebyside_len_and_push!(action_len, action_pipe<U>(me: &Action, table: Hotkey, buffer: U) {
+    let trigger = &me.key_trigger().chord;
+} {
+    "bind-key -T ";
+    table_len(table, ()) => table_pipe(table, (), buffer);
+    " ";
+    trigger.len(TMUX_CONSTANTS) => trigger.pipe(TMUX_CONSTANTS, buffer);
+    match me {
+        Action::SetState(title) => " switch-client -T ".len() + table_len(title, ()),
+        Action::Command(_, shortcut) => " run-shell -b \"\"".len()
+            + trim_command(shortcut.command).map(|frag| ESCAPE.len(frag)).sum::<usize>(),
+    } => match me {
+        Action::SetState(title) => {
+            buffer.consume(" switch-client -T ");
+            table_pipe(title, (), buffer);
+        }
+        Action::Command(_, shortcut) => {
+            buffer.consume(" run-shell -b \"");
+            trim_command(shortcut.command).for_each(|frag| ESCAPE.pipe(frag, buffer));
+            buffer.consume("\"");
+        }
+    };
+    "\n";
+});
+
+sidebyside_len_and_push!(keyspace_len, keyspace_pipe<U>(me: &Keyspace, _extra: (), buffer: U) {} {
+    me.actions.iter().map(|action| action_len(action, me.title)).sum::<usize>() =>
+        me.actions.iter().for_each(|action| action_pipe(action, me.title, buffer));
+});
+
+wrapper!(len, pipe(|_| ()));
+sidebyside_len_and_push!(len, pipe<U>(_me: (), shortcut_owner: &ShortcutOwner, buffer: U) {
+    let owner = process(shortcut_owner);
+} {
+    owner.to_iter().map(|keyspace| "\n".len() + keyspace_len(&keyspace, ())).sum::<usize>() => {
+        let mut delim = "";
+        owner.to_iter().for_each(|keyspace| {
+            buffer.consume(delim);
+            keyspace_pipe(&keyspace, (), buffer);
+            delim = "\n";
+        });
+    };
+});
+
+#[test]
+fn nested_tables() {
+    use crate::parser::parse_to_shortcuts;
+
+    let owner = parse_to_shortcuts(
+        "|ctrl alt Return| echo \"$HOME\" '#{pane_id}'\n\
+        |ctrl a ; shift {{x, semicolon}}| tmux {{kill-pane,split-window}}\n\
+        !super q! placeholder\n",
+    )
+    .unwrap();
+    assert!(verify(&owner).is_ok());
+
+    let format = crate::Format::from_str("tmux", None).unwrap();
+    let mut buffer = String::with_capacity(Wrapper().len((&owner, &format)));
+    Wrapper().pipe((&owner, &format), &mut buffer);
+    assert_eq!(
+        buffer,
+        "bind-key -T root M-C-Enter run-shell -b \"echo \\\"\\$HOME\\\" '##{pane_id}'\"\n\
+        bind-key -T root C-a switch-client -T \"C-a\"\n\
+        \n\
+        bind-key -T \"C-a\" S-x run-shell -b \"tmux kill-pane\"\n\
+        bind-key -T \"C-a\" S-\\; run-shell -b \"tmux split-window\"\n"
+    );
+
+    let owner = parse_to_shortcuts("|ctrl a ; super b| echo\n").unwrap();
+    let err = verify(&owner).unwrap_err();
+    assert_eq!((err.kind(), err.span(), err.start()), (ErrorKind::TmuxSuper, "super", 10));
+}